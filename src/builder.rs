@@ -0,0 +1,290 @@
+// Copyright 2020 Ririsoft <riri@ririsoft.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::fmt;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::{FilterErrorPolicy, Result, WalkDir, WalkDirError, WalkDirOptions};
+
+// Configures a walk, see `WalkDirBuilder::configure`.
+type SetupFn = Arc<dyn Fn(WalkDir) -> WalkDir + Send + Sync>;
+
+/// A reusable configuration, building as many independent walks as needed.
+///
+/// The options are set as on a [`WalkDir`], and the closures, such as
+/// filters or hooks, with [`configure`](Self::configure). The configuration
+/// is checked by [`build`](Self::build), unlike the one of a `WalkDir` whose
+/// conflicting options are silently ignored.
+///
+/// # Example
+///
+/// ```
+/// use async_walkdir::{Filtering, WalkDirBuilder};
+/// use futures_lite::future::ready;
+///
+/// let builder = WalkDirBuilder::new()
+///     .with_max_depth(2)
+///     .configure(|walk| walk.filter(|_| ready(Filtering::Continue)));
+/// let sources = builder.build("src").unwrap();
+/// let tests = builder.build("tests").unwrap();
+///
+/// assert!(WalkDirBuilder::new()
+///     .with_min_depth(3)
+///     .with_max_depth(2)
+///     .build("src")
+///     .is_err());
+/// ```
+#[derive(Clone, Default)]
+pub struct WalkDirBuilder {
+    options: WalkDirOptions,
+    // Applied in order to each walk built.
+    setup: Vec<SetupFn>,
+}
+
+impl WalkDirBuilder {
+    /// Returns a builder with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a builder starting from `options`.
+    pub fn new_with_options(options: WalkDirOptions) -> Self {
+        Self {
+            options,
+            setup: Vec::new(),
+        }
+    }
+
+    /// Calls `f` on each walk built, after setting the options, to set what
+    /// the options cannot hold, such as a filter, a sort or a hook.
+    ///
+    /// Closures are called in the order they are added.
+    pub fn configure<F>(mut self, f: F) -> Self
+    where
+        F: Fn(WalkDir) -> WalkDir + Send + Sync + 'static,
+    {
+        self.setup.push(Arc::new(f));
+        self
+    }
+
+    /// Returns a new walk of `root` with this configuration.
+    ///
+    /// Fails with an [`InvalidInput`](ErrorKind::InvalidInput) error if the
+    /// options conflict, as a minimum depth greater than the maximum depth.
+    pub fn build(&self, root: impl AsRef<Path>) -> Result<WalkDir> {
+        let root = root.as_ref();
+        let walk = WalkDir::new_with_options(root, self.options.clone());
+        let walk = self.setup.iter().fold(walk, |walk, f| f(walk));
+        match conflict(&walk.options) {
+            Some(conflict) => {
+                let e = Error::new(ErrorKind::InvalidInput, conflict);
+                Err(WalkDirError::new(root, e))
+            }
+            None => Ok(walk),
+        }
+    }
+
+    /// See [`WalkDir::with_filter_error_policy`].
+    pub fn with_filter_error_policy(mut self, policy: FilterErrorPolicy) -> Self {
+        self.options.filter_error_policy = policy;
+        self
+    }
+
+    /// See [`WalkDir::with_max_depth`].
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.options.max_depth = Some(depth);
+        self
+    }
+
+    /// See [`WalkDir::with_min_depth`].
+    pub fn with_min_depth(mut self, depth: usize) -> Self {
+        self.options.min_depth = depth;
+        self
+    }
+
+    /// See [`WalkDir::with_follow_links`].
+    pub fn with_follow_links(mut self, follow: bool) -> Self {
+        self.options.follow_links = follow;
+        self
+    }
+
+    /// See [`WalkDir::with_follow_root_links`].
+    pub fn with_follow_root_links(mut self, follow: bool) -> Self {
+        self.options.follow_root_links = follow;
+        self
+    }
+
+    /// See [`WalkDir::with_symlink_limit`].
+    pub fn with_symlink_limit(mut self, n: usize) -> Self {
+        self.options.symlink_limit = Some(n);
+        self
+    }
+
+    /// See [`WalkDir::with_detect_cycles`].
+    pub fn with_detect_cycles(mut self, detect: bool) -> Self {
+        self.options.detect_cycles = detect;
+        self
+    }
+
+    /// See [`WalkDir::with_same_file_system`].
+    pub fn with_same_file_system(mut self, same: bool) -> Self {
+        self.options.same_file_system = same;
+        self
+    }
+
+    /// See [`WalkDir::with_contents_first`].
+    pub fn with_contents_first(mut self, contents_first: bool) -> Self {
+        self.options.contents_first = contents_first;
+        self
+    }
+
+    /// See [`WalkDir::with_max_open`].
+    pub fn with_max_open(mut self, n: usize) -> Self {
+        self.options.max_open = Some(n);
+        self
+    }
+
+    /// See [`WalkDir::with_buffer_size`].
+    pub fn with_buffer_size(mut self, n: usize) -> Self {
+        self.options.buffer_size = Some(n);
+        self
+    }
+
+    /// See [`WalkDir::with_breadth_first`].
+    pub fn with_breadth_first(mut self, breadth_first: bool) -> Self {
+        self.options.breadth_first = breadth_first;
+        self
+    }
+
+    /// See [`WalkDir::with_ordered_by_depth`].
+    pub fn with_ordered_by_depth(mut self, ordered: bool) -> Self {
+        self.options.ordered_by_depth = ordered;
+        self
+    }
+
+    /// See [`WalkDir::with_parallelism`].
+    pub fn with_parallelism(mut self, n: usize) -> Self {
+        self.options.parallelism = Some(n);
+        self
+    }
+
+    /// See [`WalkDir::with_deduplicate`].
+    pub fn with_deduplicate(mut self, deduplicate: bool) -> Self {
+        self.options.deduplicate = deduplicate;
+        self
+    }
+
+    /// See [`WalkDir::with_error_limit`].
+    pub fn with_error_limit(mut self, n: usize) -> Self {
+        self.options.error_limit = n;
+        self
+    }
+
+    /// See [`WalkDir::with_max_io_errors_per_dir`].
+    pub fn with_max_io_errors_per_dir(mut self, n: usize) -> Self {
+        self.options.max_io_errors_per_dir = Some(n);
+        self
+    }
+
+    /// See [`WalkDir::with_max_entries`].
+    pub fn with_max_entries(mut self, n: u64) -> Self {
+        self.options.max_entries = Some(n);
+        self
+    }
+
+    /// See [`WalkDir::with_entry_limit_per_dir`].
+    pub fn with_entry_limit_per_dir(mut self, n: usize) -> Self {
+        self.options.entry_limit_per_dir = Some(n);
+        self
+    }
+
+    /// See [`WalkDir::with_max_file_size`].
+    pub fn with_max_file_size(mut self, bytes: u64) -> Self {
+        self.options.max_file_size = Some(bytes);
+        self
+    }
+
+    /// See [`WalkDir::with_min_file_size`].
+    pub fn with_min_file_size(mut self, bytes: u64) -> Self {
+        self.options.min_file_size = Some(bytes);
+        self
+    }
+
+    /// See [`WalkDir::with_prefetch_metadata`].
+    pub fn with_prefetch_metadata(mut self, prefetch: bool) -> Self {
+        self.options.prefetch_metadata = prefetch;
+        self
+    }
+
+    /// See [`WalkDir::with_gitignore`].
+    #[cfg(feature = "ignore")]
+    pub fn with_gitignore(mut self, gitignore: bool) -> Self {
+        self.options.gitignore = gitignore;
+        self
+    }
+
+    /// See [`WalkDir::with_ignore_files`].
+    #[cfg(feature = "ignore")]
+    pub fn with_ignore_files(mut self, ignore_files: bool) -> Self {
+        self.options.ignore_files = ignore_files;
+        self
+    }
+
+    /// See [`WalkDir::with_custom_ignore_file`].
+    #[cfg(feature = "ignore")]
+    pub fn with_custom_ignore_file(mut self, filename: &str) -> Self {
+        self.options.custom_ignore_files.push(filename.to_owned());
+        self
+    }
+
+    /// See [`WalkDir::exclude_path`].
+    pub fn exclude_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.options.excluded_paths.insert(path.as_ref().to_owned());
+        self
+    }
+
+    /// See [`WalkDir::with_included_paths`].
+    pub fn with_included_paths(mut self, paths: Vec<PathBuf>) -> Self {
+        self.options.included_paths = Some(paths);
+        self
+    }
+}
+
+impl fmt::Debug for WalkDirBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WalkDirBuilder")
+            .field("options", &self.options)
+            .field("setup", &self.setup.len())
+            .finish()
+    }
+}
+
+// Returns why `options` conflict, if they do.
+fn conflict(options: &WalkDirOptions) -> Option<&'static str> {
+    if options.max_depth.is_some_and(|max| options.min_depth > max) {
+        return Some("the minimum depth is greater than the maximum depth");
+    }
+    if let (Some(min), Some(max)) = (options.min_file_size, options.max_file_size) {
+        if min > max {
+            return Some("the minimum file size is greater than the maximum file size");
+        }
+    }
+    if options.contents_first
+        && (options.breadth_first || options.ordered_by_depth || options.parallelism.is_some())
+    {
+        return Some("the contents first order requires a depth first walk");
+    }
+    None
+}
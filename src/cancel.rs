@@ -0,0 +1,39 @@
+// Copyright 2020 Ririsoft <riri@ririsoft.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A token ending a walk once cancelled.
+///
+/// See [`WalkDir::with_cancellation_token`](crate::WalkDir::with_cancellation_token).
+/// It is implemented for `Arc<AtomicBool>`, cancelled once set to `true`, and
+/// for `tokio_util::sync::CancellationToken` with the `tokio-util` feature.
+pub trait CancellationToken: Send + Sync + 'static {
+    /// Returns whether the walk should end.
+    fn is_cancelled(&self) -> bool;
+}
+
+impl CancellationToken for Arc<AtomicBool> {
+    fn is_cancelled(&self) -> bool {
+        self.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(feature = "tokio-util")]
+impl CancellationToken for tokio_util::sync::CancellationToken {
+    fn is_cancelled(&self) -> bool {
+        tokio_util::sync::CancellationToken::is_cancelled(self)
+    }
+}
@@ -0,0 +1,53 @@
+// Copyright 2020 Ririsoft <riri@ririsoft.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+/// The state of a walk interrupted by a transient error.
+///
+/// Returned by [`WalkDir::into_checkpoint`](crate::WalkDir::into_checkpoint)
+/// and resumed with [`WalkDir::resume_from`](crate::WalkDir::resume_from).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WalkCheckpoint {
+    // Directories being traversed, the current one last.
+    pub(crate) dirs: Vec<CheckpointDir>,
+    // Directories queued in breadth first or parallel mode.
+    pub(crate) queue: Vec<CheckpointDir>,
+    // Roots not walked yet.
+    pub(crate) roots: Vec<PathBuf>,
+}
+
+impl WalkCheckpoint {
+    /// Returns the paths of the directories left to walk, roots included.
+    pub fn pending_dirs(&self) -> impl Iterator<Item = &PathBuf> {
+        self.dirs
+            .iter()
+            .chain(&self.queue)
+            .map(|dir| &dir.path)
+            .chain(&self.roots)
+    }
+}
+
+/// A directory of an interrupted walk.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub(crate) struct CheckpointDir {
+    pub(crate) path: PathBuf,
+    pub(crate) depth: usize,
+    // Names of the entries already walked, skipped when resuming.
+    pub(crate) walked: HashSet<OsString>,
+}
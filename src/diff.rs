@@ -0,0 +1,223 @@
+// Copyright 2020 Ririsoft <riri@ririsoft.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
+
+use futures_lite::stream::StreamExt;
+
+use crate::ext::AsyncDirEntryExt;
+use crate::{Entry, Result, WalkDir, WalkDirError};
+
+/// The changes between two snapshots of a tree.
+///
+/// Returned by [`WalkDir::diff`](crate::WalkDir::diff).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WalkDiff {
+    /// Paths only found in the second snapshot.
+    pub added: Vec<PathBuf>,
+    /// Paths only found in the first snapshot.
+    pub removed: Vec<PathBuf>,
+    /// Removed and added paths with the same file name, as `(before, after)`
+    /// pairs, which are likely renamed or moved. Those paths are not part of
+    /// `added` and `removed`.
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+}
+
+impl WalkDiff {
+    /// Returns whether the snapshots are equal.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.renamed.is_empty()
+    }
+}
+
+/// Compares two snapshots, see [`WalkDir::diff`](crate::WalkDir::diff).
+pub(crate) fn diff(before: &[PathBuf], after: &[PathBuf]) -> WalkDiff {
+    let before_set: HashSet<&Path> = before.iter().map(PathBuf::as_path).collect();
+    let after_set: HashSet<&Path> = after.iter().map(PathBuf::as_path).collect();
+    let mut removed: Vec<&PathBuf> = before
+        .iter()
+        .filter(|path| !after_set.contains(path.as_path()))
+        .collect();
+    let mut added: Vec<Option<&PathBuf>> = after
+        .iter()
+        .filter(|path| !before_set.contains(path.as_path()))
+        .map(Some)
+        .collect();
+
+    // Pairs each removed path with the first added path of the same name.
+    let mut by_name: HashMap<&OsStr, Vec<usize>> = HashMap::new();
+    for (i, path) in added.iter().enumerate().rev() {
+        if let Some(name) = path.and_then(|path| path.file_name()) {
+            by_name.entry(name).or_default().push(i);
+        }
+    }
+    let mut renamed = Vec::new();
+    removed.retain(|path| {
+        let i = path
+            .file_name()
+            .and_then(|name| by_name.get_mut(name))
+            .and_then(Vec::pop);
+        match i.and_then(|i| added[i].take()) {
+            Some(to) => {
+                renamed.push(((*path).clone(), to.clone()));
+                false
+            }
+            None => true,
+        }
+    });
+
+    WalkDiff {
+        added: added.into_iter().flatten().cloned().collect(),
+        removed: removed.into_iter().cloned().collect(),
+        renamed,
+    }
+}
+
+/// An entry differing between two trees.
+///
+/// Returned by [`WalkDir::difference`](crate::WalkDir::difference). Paths are
+/// relative to the roots of the trees.
+#[derive(Debug, Clone)]
+pub enum DiffEntry {
+    /// An entry only found in the first tree.
+    OnlyInA(PathBuf),
+    /// An entry only found in the second tree.
+    OnlyInB(PathBuf),
+    /// An entry found in both trees, of different types or, if not a
+    /// directory, of different lengths or modification times.
+    Modified {
+        /// The path of the entry.
+        path: PathBuf,
+        /// The metadata of the entry in the first tree.
+        a_meta: Box<Metadata>,
+        /// The metadata of the entry in the second tree.
+        b_meta: Box<Metadata>,
+    },
+}
+
+/// Two trees walked side by side, see
+/// [`WalkDir::difference`](crate::WalkDir::difference).
+pub(crate) struct Difference {
+    a: Side,
+    b: Side,
+}
+
+// A tree walked in name order, along with its next entry.
+struct Side {
+    root: PathBuf,
+    walk: WalkDir,
+    next: Option<(PathBuf, Entry)>,
+    done: bool,
+}
+
+impl Side {
+    fn new(root: &Path) -> Self {
+        Self {
+            root: root.to_owned(),
+            walk: WalkDir::new(root).with_sort_by_name(),
+            next: None,
+            done: false,
+        }
+    }
+
+    // Reads the next entry unless already read, returning the errors met.
+    async fn fill(&mut self) -> Option<WalkDirError> {
+        if self.next.is_some() || self.done {
+            return None;
+        }
+        match self.walk.next().await {
+            Some(Ok(entry)) => {
+                let path = entry.path();
+                let relative = path.strip_prefix(&self.root).unwrap_or(&path).to_owned();
+                self.next = Some((relative, entry));
+                None
+            }
+            Some(Err(e)) => Some(e),
+            None => {
+                self.done = true;
+                None
+            }
+        }
+    }
+}
+
+impl Difference {
+    pub(crate) fn new(root_a: &Path, root_b: &Path) -> Self {
+        Self {
+            a: Side::new(root_a),
+            b: Side::new(root_b),
+        }
+    }
+
+    // Returns the next difference, merging the trees walked in the order of
+    // their relative paths.
+    pub(crate) async fn next(&mut self) -> Option<Result<DiffEntry>> {
+        loop {
+            if let Some(e) = self.a.fill().await {
+                return Some(Err(e));
+            }
+            if let Some(e) = self.b.fill().await {
+                return Some(Err(e));
+            }
+            let order = match (&self.a.next, &self.b.next) {
+                (None, None) => return None,
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (Some((a, _)), Some((b, _))) => a.cmp(b),
+            };
+            match order {
+                Ordering::Less => {
+                    let (path, _) = self.a.next.take()?;
+                    return Some(Ok(DiffEntry::OnlyInA(path)));
+                }
+                Ordering::Greater => {
+                    let (path, _) = self.b.next.take()?;
+                    return Some(Ok(DiffEntry::OnlyInB(path)));
+                }
+                Ordering::Equal => {
+                    let (path, a) = self.a.next.take()?;
+                    let (_, b) = self.b.next.take()?;
+                    let a_meta = match a.async_metadata().await {
+                        Ok(meta) => meta,
+                        Err(e) => return Some(Err(WalkDirError::new(a.path(), e))),
+                    };
+                    let b_meta = match b.async_metadata().await {
+                        Ok(meta) => meta,
+                        Err(e) => return Some(Err(WalkDirError::new(b.path(), e))),
+                    };
+                    if is_modified(&a_meta, &b_meta) {
+                        return Some(Ok(DiffEntry::Modified {
+                            path,
+                            a_meta: Box::new(a_meta),
+                            b_meta: Box::new(b_meta),
+                        }));
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Returns whether the metadata of an entry found in both trees differ.
+fn is_modified(a: &Metadata, b: &Metadata) -> bool {
+    if a.file_type() != b.file_type() {
+        return true;
+    }
+    // The modification time of a directory changes with its entries.
+    !a.is_dir() && (a.len() != b.len() || a.modified().ok() != b.modified().ok())
+}
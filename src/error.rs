@@ -0,0 +1,81 @@
+// Copyright 2020 Ririsoft <riri@ririsoft.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// An error produced while walking a directory.
+///
+/// It records the path being processed when the underlying IO error occurred,
+/// and dereferences to that [`io::Error`] so that, for instance,
+/// [`kind`](io::Error::kind) can be called directly.
+#[derive(Debug)]
+pub struct WalkDirError {
+    path: PathBuf,
+    err: io::Error,
+}
+
+impl WalkDirError {
+    pub(crate) fn new(path: impl Into<PathBuf>, err: io::Error) -> Self {
+        Self {
+            path: path.into(),
+            err,
+        }
+    }
+
+    /// Returns the path being processed when the error occurred.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the underlying IO error.
+    pub fn io_error(&self) -> &io::Error {
+        &self.err
+    }
+
+    /// Converts into the underlying IO error, dropping the path.
+    pub fn into_io_error(self) -> io::Error {
+        self.err
+    }
+}
+
+impl Deref for WalkDirError {
+    type Target = io::Error;
+
+    fn deref(&self) -> &Self::Target {
+        &self.err
+    }
+}
+
+impl fmt::Display for WalkDirError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "walking {}: {}", self.path.display(), self.err)
+    }
+}
+
+impl Error for WalkDirError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.err)
+    }
+}
+
+impl From<WalkDirError> for io::Error {
+    /// Keeps the error kind, the path being part of the message.
+    fn from(err: WalkDirError) -> Self {
+        io::Error::new(err.err.kind(), err)
+    }
+}
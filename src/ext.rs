@@ -0,0 +1,69 @@
+// Copyright 2020 Ririsoft <riri@ririsoft.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Extension traits for directory entries.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use async_walkdir::ext::AsyncDirEntryExt;
+//! use async_walkdir::{Filtering, WalkDir};
+//!
+//! let entries = WalkDir::new("my_directory").filter(|entry| async move {
+//!     match entry.async_metadata().await {
+//!         Ok(metadata) if metadata.len() > 1024 => Filtering::Continue,
+//!         _ => Filtering::Ignore,
+//!     }
+//! });
+//! ```
+
+use std::fs::{self, DirEntry, Metadata};
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+
+use crate::Entry;
+
+/// The future returned by [`AsyncDirEntryExt::async_metadata`].
+pub type MetadataFuture<'a> = Pin<Box<dyn Future<Output = io::Result<Metadata>> + Send + 'a>>;
+
+/// Asynchronous accessors for directory entries.
+pub trait AsyncDirEntryExt {
+    /// Returns the metadata of the entry, read on a thread pool.
+    ///
+    /// As [`DirEntry::metadata`], symbolic links are not traversed.
+    fn async_metadata(&self) -> MetadataFuture<'_>;
+}
+
+impl AsyncDirEntryExt for DirEntry {
+    fn async_metadata(&self) -> MetadataFuture<'_> {
+        let path = self.path();
+        Box::pin(blocking::unblock(move || fs::symlink_metadata(path)))
+    }
+}
+
+/// Returns the metadata attached to the entry if any, otherwise reads it and
+/// attaches it to the entry.
+impl AsyncDirEntryExt for Entry {
+    fn async_metadata(&self) -> MetadataFuture<'_> {
+        Box::pin(async move {
+            if let Some(metadata) = self.cached_metadata() {
+                return Ok(metadata.clone());
+            }
+            let metadata = (**self).async_metadata().await?;
+            self.set_metadata(metadata.clone());
+            Ok(metadata)
+        })
+    }
+}
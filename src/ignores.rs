@@ -0,0 +1,131 @@
+// Copyright 2020 Ririsoft <riri@ririsoft.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Ignore files, such as `.gitignore`, applied while walking.
+//!
+//! Without the `ignore` feature, no ignore file is ever read.
+
+#[cfg(feature = "ignore")]
+use std::fs;
+use std::io;
+#[cfg(feature = "ignore")]
+use std::io::ErrorKind;
+use std::path::Path;
+use std::sync::Arc;
+
+#[cfg(feature = "ignore")]
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+#[cfg(feature = "ignore")]
+use ignore::Match;
+
+use crate::WalkDirOptions;
+
+/// Returns the names of the ignore files to read in each directory, the ones
+/// taking precedence last.
+#[cfg(feature = "ignore")]
+pub(crate) fn file_names(options: &WalkDirOptions) -> Vec<String> {
+    let mut names = Vec::new();
+    if options.gitignore {
+        names.push(".gitignore".to_owned());
+    }
+    if options.ignore_files {
+        names.push(".ignore".to_owned());
+    }
+    names.extend(options.custom_ignore_files.iter().cloned());
+    names
+}
+
+/// Returns the names of the ignore files to read in each directory, the ones
+/// taking precedence last.
+#[cfg(not(feature = "ignore"))]
+pub(crate) fn file_names(_options: &WalkDirOptions) -> Vec<String> {
+    Vec::new()
+}
+
+/// The ignore rules applying to the entries of a directory.
+///
+/// Rules read from a directory take precedence over the ones of its parents.
+#[cfg(feature = "ignore")]
+pub(crate) struct Ignores {
+    matcher: Gitignore,
+    parent: Option<Arc<Ignores>>,
+}
+
+#[cfg(feature = "ignore")]
+impl Ignores {
+    /// Reads the ignore files named `names` in `dir`.
+    ///
+    /// Returns `parent` when `dir` has none of them. Invalid patterns are
+    /// skipped.
+    pub(crate) fn load(
+        dir: &Path,
+        names: &[String],
+        parent: Option<Arc<Ignores>>,
+    ) -> io::Result<Option<Arc<Ignores>>> {
+        let mut builder = GitignoreBuilder::new(dir);
+        let mut found = false;
+        for name in names {
+            let path = dir.join(name);
+            let contents = match fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e),
+            };
+            for line in contents.lines() {
+                let _ = builder.add_line(Some(path.clone()), line);
+            }
+            found = true;
+        }
+        if !found {
+            return Ok(parent);
+        }
+        let matcher = builder.build().map_err(io::Error::other)?;
+        Ok(Some(Arc::new(Ignores { matcher, parent })))
+    }
+
+    /// Returns whether the entry at `path` is ignored.
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut ignores = Some(self);
+        while let Some(current) = ignores {
+            match current.matcher.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => ignores = current.parent.as_deref(),
+            }
+        }
+        false
+    }
+}
+
+/// The ignore rules applying to the entries of a directory, never built
+/// without the `ignore` feature.
+#[cfg(not(feature = "ignore"))]
+pub(crate) enum Ignores {}
+
+#[cfg(not(feature = "ignore"))]
+impl Ignores {
+    /// Returns `parent`, no ignore file being read.
+    pub(crate) fn load(
+        _dir: &Path,
+        _names: &[String],
+        parent: Option<Arc<Ignores>>,
+    ) -> io::Result<Option<Arc<Ignores>>> {
+        Ok(parent)
+    }
+
+    /// Returns whether the entry at `path` is ignored.
+    pub(crate) fn is_ignored(&self, _path: &Path, _is_dir: bool) -> bool {
+        match *self {}
+    }
+}
@@ -43,6 +43,11 @@
 //! });
 //! ```
 //!
+//! Filters and consumers receive an [`Entry`], which dereferences to a
+//! [`std::fs::DirEntry`] whose methods are blocking. The file type is cached
+//! by the walk, so [`Entry::file_type`] never blocks, and
+//! [`ext::AsyncDirEntryExt`] reads the metadata on the thread pool.
+//!
 //! Do not recurse through directories whose name starts with '.':
 //!
 //! ```
@@ -74,40 +79,152 @@
 //!     }
 //! });
 //! ```
+//!
+//! The `glob`, `regex` and `ignore` features, disabled by default, add the
+//! `filter_glob` and `filter_regex` filters and the `.gitignore` support.
 
 #![forbid(unsafe_code)]
 #![deny(missing_docs)]
+#![cfg_attr(feature = "async_iterator", feature(async_iterator))]
 
-use std::{fs::{DirEntry, ReadDir, read_dir}, future::Future, sync::Arc};
+use std::cmp;
+use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::ffi::OsString;
+use std::fs::{self, read_dir, DirEntry, ReadDir};
+use std::future::Future;
+use std::io::{self, Error};
+use std::ops::Deref;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
 use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
 
 use futures_lite::future::Boxed as BoxedFut;
-use futures_lite::future::FutureExt;
+use futures_lite::future::{self, FutureExt};
+use futures_lite::ready;
 use futures_lite::stream::{self, Stream, StreamExt};
 
-#[doc(no_inline)]
-pub use std::io::Result;
+use checkpoint::CheckpointDir;
+use ext::AsyncDirEntryExt;
+use ignores::Ignores;
+use rate::RateLimiter;
+
+mod builder;
+mod cancel;
+mod checkpoint;
+mod diff;
+mod error;
+pub mod ext;
+mod ignores;
+mod memory;
+mod peekable;
+#[cfg(feature = "tokio")]
+mod progress;
+mod rate;
+mod root;
+mod stats;
+mod sys;
+
+pub use builder::WalkDirBuilder;
+pub use cancel::CancellationToken;
+pub use checkpoint::WalkCheckpoint;
+pub use diff::{DiffEntry, WalkDiff};
+pub use error::WalkDirError;
+pub use memory::{VirtualEntry, VirtualFileType, VirtualTree, VirtualWalkDir};
+pub use peekable::PeekableWalkDir;
+#[cfg(feature = "tokio")]
+pub use progress::WalkProgress;
+pub use root::{RootEntry, RootedEntry, RootedWalkDir};
+pub use stats::WalkDirStats;
+
+// Emits a trace event when the `tracing` feature is enabled.
+macro_rules! trace {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "tracing")]
+        tracing::trace!($($arg)*);
+    };
+}
+
+/// A specialized `Result` type for walk operations.
+pub type Result<T, E = WalkDirError> = std::result::Result<T, E>;
+
+type BoxStream = futures_lite::stream::Boxed<Result<RootedEntry>>;
 
-type BoxStream = futures_lite::stream::Boxed<Result<Arc<DirEntry>>>;
+// Shared with the clones of the walk, see `WalkDir::try_clone`.
+type FilterFn = Arc<Mutex<dyn FnMut(Arc<Entry>) -> BoxedFut<io::Result<Filtering>> + Send>>;
+
+type HookFn = Box<dyn FnMut(&Entry) + Send>;
+
+type DirHookFn = Box<dyn FnMut(&Path) + Send>;
+
+// Sleeps for the given duration, see `WalkDir::with_rate_limit`.
+type SleepFn = Arc<dyn Fn(Duration) -> BoxedFut<()> + Send + Sync>;
+
+type SortFn = Arc<dyn Fn(&DirEntry, &DirEntry) -> cmp::Ordering + Send + Sync>;
+
+/// Opens a directory, see [`WalkDir::with_open_dir_fn`].
+pub type OpenDirFn = Arc<dyn Fn(&Path) -> io::Result<ReadDir> + Send + Sync>;
+
+type CheckpointSlot = Arc<Mutex<Option<WalkCheckpoint>>>;
 
 /// A `Stream` of `DirEntry` generated from recursively traversing
 /// a directory.
 ///
 /// Entries are returned without a specific ordering. The top most root directory
-/// is not returned but child directories are.
+/// is not returned but child directories are, see
+/// [`with_root_entry`](WalkDir::with_root_entry).
+///
+/// A `WalkDir` is `Send`, so that it can be walked on a multi-threaded
+/// runtime, but not `Sync`: the closures it holds are only required to be
+/// `Send`, and a stream is only ever polled through a mutable reference.
 ///
 /// # Panics
 ///
 /// Panics if the directories depth overflows `usize`.
 pub struct WalkDir {
+    roots: Vec<PathBuf>,
+    // The already open root, see `from_read_dir`.
+    root_read_dir: Option<ReadDir>,
+    options: WalkDirOptions,
+    filter: Option<FilterFn>,
+    sort: Option<Sort>,
+    stats: Option<Arc<WalkDirStats>>,
+    // Entries per second, see `with_rate_limit`.
+    rate_limit: Option<(f64, SleepFn)>,
+    open_dir: Option<OpenDirFn>,
+    checkpoint: Option<CheckpointSlot>,
+    resume: Option<WalkCheckpoint>,
+    cancellation: Option<Arc<dyn CancellationToken>>,
+    // See `with_timeout`.
+    timeout: Option<(Duration, SleepFn)>,
+    // Started with the walk.
+    deadline: Option<Deadline>,
+    hooks: Vec<HookFn>,
+    dir_enter_hooks: Vec<DirHookFn>,
+    dir_exit_hooks: Vec<DirHookFn>,
+    #[cfg(feature = "tokio")]
+    progress: Option<tokio::sync::watch::Sender<WalkProgress>>,
+    entries: Option<Walking>,
+    // Whether the stream ended, see `FusedStream`.
+    terminated: bool,
+    // Whether the roots are yielded, see `with_root_entry`.
+    root_entry: bool,
+}
+
+// Elapses when a walk times out.
+struct Deadline {
+    duration: Duration,
+    sleep: SleepFn,
+    // Started when the stream is first polled.
+    timer: Option<BoxedFut<()>>,
+    // The root reported in the timeout error.
     root: PathBuf,
-    entries: BoxStream,
 }
 
 /// Sets the filtering behavior.
 #[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Filtering {
     /// Ignore the current entry.
     Ignore,
@@ -116,269 +233,6479 @@ pub enum Filtering {
     IgnoreDir,
     /// Continue the normal processing.
     Continue,
+    /// Return the current entry and stop the walk.
+    ///
+    /// Directories not traversed yet are not read any further and the
+    /// stream ends right after this entry.
+    Stop,
+    /// Ignore the current entry and stop the walk.
+    ///
+    /// The stream ends without returning this entry.
+    IgnoreAndStop,
 }
 
-impl WalkDir {
-    /// Returns a new `Walkdir` starting at `root`.
-    pub fn new(root: impl AsRef<Path>) -> Self {
+/// A `DirEntry` returned by [`WalkDir`] along with its depth, file type and
+/// metadata.
+///
+/// It dereferences to the underlying [`std::fs::DirEntry`]. The file type is
+/// read when walking the entry, and the metadata on first access only unless
+/// prefetched, then both are cached.
+#[derive(Debug)]
+pub struct Entry {
+    entry: DirEntry,
+    depth: usize,
+    dir: Arc<Path>,
+    file_type: fs::FileType,
+    metadata: OnceLock<fs::Metadata>,
+}
+
+/// The former name of [`Entry`].
+#[deprecated(note = "renamed to `Entry`")]
+pub type DirEntryExt = Entry;
+
+impl Entry {
+    fn new(entry: DirEntry, depth: usize, dir: Arc<Path>, file_type: fs::FileType) -> Self {
         Self {
-            root: root.as_ref().to_owned(),
-            entries: walk_dir(
-                root,
-                None::<Box<dyn FnMut(Arc<DirEntry>) -> BoxedFut<Filtering> + Send>>,
-            ),
+            entry,
+            depth,
+            dir,
+            file_type,
+            metadata: OnceLock::new(),
         }
     }
 
-    /// Filter entries.
-    pub fn filter<F, Fut>(self, f: F) -> Self
-    where
-        F: FnMut(Arc<DirEntry>) -> Fut + Send + 'static,
-        Fut: Future<Output = Filtering> + Send,
-    {
-        let root = self.root.clone();
-        Self {
-            root: self.root,
-            entries: walk_dir(root, Some(f)),
+    /// Returns the underlying [`std::fs::DirEntry`].
+    pub fn into_inner(self) -> DirEntry {
+        self.entry
+    }
+
+    /// Returns the depth of the entry relative to the walk root.
+    ///
+    /// The direct children of the root are at depth 0.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the path of the directory the entry was read from.
+    ///
+    /// This is the path of the directory as walked, thus relative if the root
+    /// is, unlike the parent of [`path`](DirEntry::path) it is never empty.
+    pub fn containing_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Returns the file type of the entry, without any system call.
+    ///
+    /// As [`DirEntry::file_type`], symbolic links are not traversed. It never
+    /// fails, the result being kept for compatibility with
+    /// [`DirEntry::file_type`].
+    pub fn file_type(&self) -> io::Result<fs::FileType> {
+        Ok(self.file_type)
+    }
+
+    /// Returns the metadata of the entry, read on first access and cached
+    /// afterwards.
+    ///
+    /// As [`DirEntry::metadata`], symbolic links are not traversed. Errors are
+    /// not cached.
+    pub fn metadata(&self) -> io::Result<fs::Metadata> {
+        if let Some(metadata) = self.cached_metadata() {
+            return Ok(metadata.clone());
         }
+        let metadata = self.entry.metadata()?;
+        self.set_metadata(metadata.clone());
+        Ok(metadata)
     }
-}
 
-impl Stream for WalkDir {
-    type Item = Result<Arc<DirEntry>>;
+    /// Attaches `metadata` to the entry, so that it can be retrieved later
+    /// with [`cached_metadata`](Self::cached_metadata) without another system
+    /// call.
+    ///
+    /// A filter can thus attach metadata to the entry yielded by the stream,
+    /// as the size and time filters do. The metadata is expected
+    /// to be the one returned by [`DirEntry::metadata`], which does not
+    /// traverse symbolic links. Metadata already attached is kept.
+    pub fn set_metadata(&self, metadata: fs::Metadata) {
+        let _ = self.metadata.set(metadata);
+    }
 
-    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let entries = Pin::new(&mut self.entries);
-        entries.poll_next(cx)
+    /// Returns the metadata attached to the entry, if any.
+    ///
+    /// See [`set_metadata`](Self::set_metadata) and
+    /// [`WalkDir::with_prefetch_metadata`].
+    pub fn cached_metadata(&self) -> Option<&fs::Metadata> {
+        self.metadata.get()
     }
 }
 
-fn walk_dir<F, Fut>(root: impl AsRef<Path>, filter: Option<F>) -> BoxStream
-where
-    F: FnMut(Arc<DirEntry>) -> Fut + Send + 'static,
-    Fut: Future<Output = Filtering> + Send,
-{
-    stream::unfold(
-        State::Start((root.as_ref().to_owned(), filter)),
-        move |state| async move {
-            match state {
-                State::Start((root, filter)) => match read_dir(root){
-                    Err(e) => return Some((Err(e), State::Done)),
-                    Ok(rd) => return walk(vec![rd], filter).await,
-                },
-                State::Walk((dirs, filter)) => return walk(dirs, filter).await,
-                State::Done => return None,
+impl Entry {
+    // Reads the entry again from its directory, as a `DirEntry` cannot be
+    // cloned, keeping what was read when walking it.
+    fn read_again(
+        &self,
+        open_dir: &(dyn Fn(&Path) -> io::Result<ReadDir> + Send + Sync),
+    ) -> io::Result<Self> {
+        let name = self.file_name();
+        for entry in open_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_name() == name {
+                return Ok(Self {
+                    entry,
+                    depth: self.depth,
+                    dir: self.dir.clone(),
+                    file_type: self.file_type,
+                    metadata: self.metadata.clone(),
+                });
             }
-        },
-    )
-    .boxed()
+        }
+        Err(Error::new(
+            io::ErrorKind::NotFound,
+            "the entry was removed from its directory",
+        ))
+    }
 }
 
-enum State<F> {
-    Start((PathBuf, Option<F>)),
-    Walk((Vec<ReadDir>, Option<F>)),
-    Done,
+impl Deref for Entry {
+    type Target = DirEntry;
+
+    fn deref(&self) -> &Self::Target {
+        &self.entry
+    }
 }
 
-type UnfoldState<F> = (Result<Arc<DirEntry>>, State<F>);
+/// An entry returned by [`WalkDir::with_path_transform`], whose path was
+/// rewritten.
+///
+/// It dereferences to the underlying [`Entry`], except for
+/// [`path`](Self::path) which returns the rewritten path.
+#[derive(Debug)]
+pub struct TransformedEntry {
+    entry: Entry,
+    path: PathBuf,
+}
 
-fn walk<F, Fut>(mut dirs: Vec<ReadDir>, filter: Option<F>) -> BoxedFut<Option<UnfoldState<F>>>
-where
-    F: FnMut(Arc<DirEntry>) -> Fut + Send + 'static,
-    Fut: Future<Output = Filtering> + Send,
-{
-    async move {
-        if let Some(dir) = dirs.last_mut() {
-            match dir.next(){
-                Some(Ok(entry)) => walk_entry(entry, dirs, filter).await,
-                Some(Err(e)) => Some((Err(e), State::Walk((dirs, filter)))),
-                None => {
-                    dirs.pop();
-                    walk(dirs, filter).await
-                }
-            }
-        } else {
-            None
-        }
+impl TransformedEntry {
+    /// Returns the rewritten path of the entry.
+    pub fn path(&self) -> PathBuf {
+        self.path.clone()
+    }
+
+    /// Returns the underlying [`std::fs::DirEntry`], whose path is the
+    /// original one.
+    pub fn raw(&self) -> &DirEntry {
+        &self.entry
+    }
+
+    /// Returns the underlying [`Entry`].
+    pub fn into_inner(self) -> Entry {
+        self.entry
     }
-    .boxed()
 }
 
-fn walk_entry<F, Fut>(
-    entry: DirEntry,
-    mut dirs: Vec<ReadDir>,
-    mut filter: Option<F>,
-) -> BoxedFut<Option<UnfoldState<F>>>
-where
-    F: FnMut(Arc<DirEntry>) -> Fut + Send + 'static,
-    Fut: Future<Output = Filtering> + Send,
-{
-    let entry = Arc::new(entry);
-    async move {
-        match entry.file_type(){
-            Err(e) => Some((Err(e), State::Walk((dirs, filter)))),
-            Ok(ft) => {
-                let filtering = match filter.as_mut() {
-                    Some(filter) => filter(entry.clone()).await,
-                    None => Filtering::Continue,
-                };
-                if ft.is_dir() {
-                    let rd = match read_dir(entry.path()){
-                        Err(e) => return Some((Err(e), State::Walk((dirs, filter)))),
-                        Ok(rd) => rd,
-                    };
-                    if filtering != Filtering::IgnoreDir {
-                        dirs.push(rd);
-                    }
-                }
-                match filtering {
-                    Filtering::Continue => Some((Ok(entry), State::Walk((dirs, filter)))),
-                    Filtering::IgnoreDir | Filtering::Ignore => walk(dirs, filter).await,
-                }
-            }
-        }
+impl Deref for TransformedEntry {
+    type Target = Entry;
+
+    fn deref(&self) -> &Self::Target {
+        &self.entry
     }
-    .boxed()
 }
 
-#[cfg(test)]
-mod tests {
-    use std::io::{ErrorKind, Result};
+/// The configuration of a [`WalkDir`].
+///
+/// Each field matches a `WalkDir::with_*` method, whose documentation gives the
+/// details. The default values walk the whole tree without following links.
+///
+/// A `WalkDir` does not touch the file system before its stream is first
+/// polled, so the options are the configuration to build once and reuse to
+/// start several independent walks, with
+/// [`WalkDir::new_with_options`]. See [`WalkDirBuilder`] to also reuse the
+/// closures, such as filters, and check that the options do not conflict.
+///
+/// # Example
+///
+/// ```
+/// use async_walkdir::{WalkDir, WalkDirOptions};
+///
+/// let options = WalkDirOptions {
+///     max_depth: Some(2),
+///     follow_links: true,
+///     ..Default::default()
+/// };
+/// let sources = WalkDir::new_with_options("src", options.clone());
+/// let tests = WalkDir::new_with_options("tests", options);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WalkDirOptions {
+    /// See [`WalkDir::with_max_depth`].
+    pub max_depth: Option<usize>,
+    /// See [`WalkDir::with_min_depth`].
+    pub min_depth: usize,
+    /// See [`WalkDir::with_follow_links`].
+    pub follow_links: bool,
+    /// See [`WalkDir::with_follow_root_links`].
+    pub follow_root_links: bool,
+    /// See [`WalkDir::with_symlink_limit`].
+    pub symlink_limit: Option<usize>,
+    /// See [`WalkDir::with_detect_cycles`].
+    pub detect_cycles: bool,
+    /// See [`WalkDir::with_same_file_system`].
+    pub same_file_system: bool,
+    /// See [`WalkDir::with_contents_first`].
+    pub contents_first: bool,
+    /// See [`WalkDir::with_max_open`].
+    pub max_open: Option<usize>,
+    /// See [`WalkDir::with_buffer_size`].
+    pub buffer_size: Option<usize>,
+    /// See [`WalkDir::with_breadth_first`].
+    pub breadth_first: bool,
+    /// See [`WalkDir::with_ordered_by_depth`].
+    pub ordered_by_depth: bool,
+    /// See [`WalkDir::with_parallelism`].
+    pub parallelism: Option<usize>,
+    /// See [`WalkDir::with_prefetch_metadata`].
+    pub prefetch_metadata: bool,
+    /// See [`WalkDir::with_max_entries`].
+    pub max_entries: Option<u64>,
+    /// See [`WalkDir::with_entry_limit_per_dir`].
+    pub entry_limit_per_dir: Option<usize>,
+    /// See [`WalkDir::with_max_file_size`].
+    pub max_file_size: Option<u64>,
+    /// See [`WalkDir::with_min_file_size`].
+    pub min_file_size: Option<u64>,
+    /// See [`WalkDir::with_deduplicate`].
+    pub deduplicate: bool,
+    /// See [`WalkDir::with_error_limit`].
+    pub error_limit: usize,
+    /// See [`WalkDir::with_max_io_errors_per_dir`].
+    pub max_io_errors_per_dir: Option<usize>,
+    /// See [`WalkDir::with_filter_error_policy`].
+    pub filter_error_policy: FilterErrorPolicy,
+    /// See [`WalkDir::with_gitignore`].
+    #[cfg(feature = "ignore")]
+    pub gitignore: bool,
+    /// See [`WalkDir::with_ignore_files`].
+    #[cfg(feature = "ignore")]
+    pub ignore_files: bool,
+    /// See [`WalkDir::with_custom_ignore_file`].
+    #[cfg(feature = "ignore")]
+    pub custom_ignore_files: Vec<String>,
+    /// See [`WalkDir::exclude_path`].
+    pub excluded_paths: HashSet<PathBuf>,
+    /// See [`WalkDir::with_included_paths`].
+    pub included_paths: Option<Vec<PathBuf>>,
+}
 
-    use futures_lite::future::block_on;
-    use futures_lite::stream::StreamExt;
+/// Sets the behavior when a filter fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FilterErrorPolicy {
+    /// Skip the entry being filtered and go on with the walk.
+    #[default]
+    Skip,
+    /// Stop the walk after returning the error.
+    Stop,
+}
 
-    use super::{Filtering, WalkDir};
+impl WalkDir {
+    /// Returns a new `Walkdir` starting at `root`.
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self::new_multi(Some(root))
+    }
 
-    #[test]
-    fn walk_dir_empty() -> Result<()> {
-        block_on(async {
-            let root = tempfile::tempdir()?;
-            let mut wd = WalkDir::new(root.path());
-            assert!(wd.next().await.is_none());
-            Ok(())
-        })
+    /// Returns a new `Walkdir` starting at `root` and configured with `options`.
+    pub fn new_with_options(root: impl AsRef<Path>, options: WalkDirOptions) -> Self {
+        Self {
+            options,
+            ..Self::new(root)
+        }
     }
 
-    #[test]
-    fn walk_dir_not_exist() {
-        block_on(async {
-            let mut wd = WalkDir::new("foobar");
-            match wd.next().await.unwrap() {
-                Ok(_) => panic!("want error"),
-                Err(e) => assert_eq!(e.kind(), ErrorKind::NotFound),
-            }
+    /// Returns a new `Walkdir` starting at each of `roots`.
+    ///
+    /// Roots are walked one after the other, in the given order: all the
+    /// entries of a root are returned before the ones of the next root.
+    /// An error on a root, such as a root that does not exist, does not
+    /// prevent the next roots from being walked.
+    pub fn new_multi(roots: impl IntoIterator<Item = impl AsRef<Path>>) -> Self {
+        Self {
+            roots: roots
+                .into_iter()
+                .map(|root| root.as_ref().to_owned())
+                .collect(),
+            root_read_dir: None,
+            options: WalkDirOptions::default(),
+            filter: None,
+            sort: None,
+            stats: None,
+            rate_limit: None,
+            open_dir: None,
+            checkpoint: None,
+            resume: None,
+            cancellation: None,
+            timeout: None,
+            deadline: None,
+            hooks: Vec::new(),
+            dir_enter_hooks: Vec::new(),
+            dir_exit_hooks: Vec::new(),
+            #[cfg(feature = "tokio")]
+            progress: None,
+            entries: None,
+            terminated: false,
+            root_entry: false,
+        }
+    }
+
+    /// Returns a new `Walkdir` starting at `root`, whose entries are read
+    /// from `rd`.
+    ///
+    /// `rd` is expected to be the result of [`read_dir`]
+    /// on `root`, which is then not read again. This saves a system call when
+    /// the caller already opened the directory, on a network file system for
+    /// instance.
+    pub fn from_read_dir(rd: ReadDir, root: impl AsRef<Path>) -> Self {
+        Self {
+            root_read_dir: Some(rd),
+            ..Self::new(root)
+        }
+    }
+
+    /// Filter entries.
+    ///
+    /// The entry is shared with `f` until the returned future completes. As a
+    /// [`std::fs::DirEntry`] cannot be cloned, an entry still referenced
+    /// afterwards is read again from its directory on the thread pool before
+    /// being yielded, keeping its depth, file type and cached metadata.
+    ///
+    /// [`Entry::file_type`] is read beforehand and never blocks, unlike the
+    /// other accessors of [`std::fs::DirEntry`] which block the task. Use
+    /// [`AsyncDirEntryExt::async_metadata`] to read the metadata on the thread
+    /// pool instead.
+    pub fn filter<F, Fut>(mut self, mut f: F) -> Self
+    where
+        F: FnMut(Arc<Entry>) -> Fut + Send + 'static,
+        Fut: Future<Output = Filtering> + Send + 'static,
+    {
+        self.filter = Some(Arc::new(Mutex::new(move |entry| {
+            let filtering = f(entry);
+            async move { Ok(filtering.await) }.boxed()
+        })));
+        self
+    }
+
+    /// Filter entries, `f` receiving the path of the directory containing
+    /// each entry as well.
+    ///
+    /// The path is the one returned by [`Entry::containing_dir`]. See
+    /// [`filter`](Self::filter).
+    pub fn filter_with_context<F, Fut>(self, mut f: F) -> Self
+    where
+        F: FnMut(Arc<Entry>, &Path) -> Fut + Send + 'static,
+        Fut: Future<Output = Filtering> + Send + 'static,
+    {
+        self.filter(move |entry| {
+            let dir = entry.dir.clone();
+            f(entry, &dir)
         })
     }
 
-    #[test]
-    fn walk_dir_files() -> Result<()> {
-        block_on(async {
-            let root = tempfile::tempdir()?;
-            let f1 = root.path().join("f1.txt");
-            let d1 = root.path().join("d1");
-            let f2 = d1.join("f2.txt");
-            let d2 = d1.join("d2");
-            let f3 = d2.join("f3.txt");
+    /// Filter entries with a function that may fail.
+    ///
+    /// An error returned by `f` is yielded by the stream, along with the path
+    /// of the entry being filtered. That entry is then neither returned nor
+    /// traversed, and the walk goes on or stops according to
+    /// [`with_filter_error_policy`](Self::with_filter_error_policy).
+    pub fn try_filter<F, Fut>(mut self, mut f: F) -> Self
+    where
+        F: FnMut(Arc<Entry>) -> Fut + Send + 'static,
+        Fut: Future<Output = io::Result<Filtering>> + Send + 'static,
+    {
+        self.filter = Some(Arc::new(Mutex::new(move |entry| f(entry).boxed())));
+        self
+    }
 
-            async_fs::create_dir_all(&d2).await?;
-            async_fs::write(&f1, []).await?;
-            async_fs::write(&f2, []).await?;
-            async_fs::write(&f3, []).await?;
+    /// Sets what to do when a filter set with [`try_filter`](Self::try_filter)
+    /// returns an error.
+    pub fn with_filter_error_policy(mut self, policy: FilterErrorPolicy) -> Self {
+        self.options.filter_error_policy = policy;
+        self
+    }
 
-            let want = vec![
-                d1.to_owned(),
-                d2.to_owned(),
-                f3.to_owned(),
-                f2.to_owned(),
-                f1.to_owned(),
-            ];
-            let mut wd = WalkDir::new(root.path());
+    /// Filter entries with a synchronous function.
+    ///
+    /// This is a shorthand for [`filter`](Self::filter) when the filtering
+    /// decision does not need to await anything.
+    pub fn filter_entry<F>(self, f: F) -> Self
+    where
+        F: Fn(&Entry) -> Filtering + Send + 'static,
+    {
+        self.filter(move |entry| future::ready(f(&entry)))
+    }
 
-            let mut got = Vec::new();
-            while let Some(entry) = wd.next().await {
-                let entry = entry.unwrap();
-                got.push(entry.path());
-            }
-            got.sort();
-            assert_eq!(got, want);
+    /// Ignores the entries whose path does not match the glob `pattern`.
+    ///
+    /// The pattern is matched against the full path of each entry, following
+    /// the [glob](https://docs.rs/glob) crate syntax, `*.rs` or `**/tests/**`
+    /// for instance. Non matching directories are still traversed. The filter
+    /// is added to the current ones, as with [`and_filter`](Self::and_filter).
+    ///
+    /// Returns an error if `pattern` is invalid. Requires the `glob` feature.
+    #[cfg(feature = "glob")]
+    pub fn filter_glob(self, pattern: &str) -> Result<Self, glob::PatternError> {
+        let pattern = glob::Pattern::new(pattern)?;
+        Ok(self.and_filter(move |entry| {
+            future::ready(if pattern.matches_path(&entry.path()) {
+                Filtering::Continue
+            } else {
+                Filtering::Ignore
+            })
+        }))
+    }
 
-            Ok(())
+    /// Ignores the entries whose path does not match the regular expression `pattern`.
+    ///
+    /// The expression is compiled once and searched in the full path of each
+    /// entry, `\.rs$` for instance. Paths that are not valid UTF-8 never match.
+    /// Non matching directories are still traversed. The filter is added to the
+    /// current ones, as with [`and_filter`](Self::and_filter).
+    ///
+    /// Returns an error if `pattern` is invalid. Requires the `regex` feature.
+    #[cfg(feature = "regex")]
+    pub fn filter_regex(self, pattern: &str) -> Result<Self, regex::Error> {
+        let regex = regex::Regex::new(pattern)?;
+        Ok(self.and_filter(move |entry| {
+            let path = entry.path();
+            future::ready(match path.to_str() {
+                Some(path) if regex.is_match(path) => Filtering::Continue,
+                _ => Filtering::Ignore,
+            })
+        }))
+    }
+
+    /// Only yields the files with the extension `ext`, given without the leading dot.
+    ///
+    /// Directories are traversed but not yielded. Extensions are compared case
+    /// insensitively on Windows and case sensitively elsewhere. The filter is
+    /// added to the current ones, as with [`and_filter`](Self::and_filter).
+    pub fn filter_extension(self, ext: &str) -> Self {
+        let ext = OsString::from(ext);
+        self.and_filter(move |entry| {
+            let matches = match entry.path().extension() {
+                Some(e) if cfg!(windows) => e.eq_ignore_ascii_case(&ext),
+                Some(e) => e == ext,
+                None => false,
+            };
+            future::ready(match entry.file_type() {
+                Ok(ft) if !ft.is_dir() && matches => Filtering::Continue,
+                _ => Filtering::Ignore,
+            })
         })
     }
 
-    #[test]
-    fn filter_dirs() -> Result<()> {
-        block_on(async {
-            let root = tempfile::tempdir()?;
-            let f1 = root.path().join("f1.txt");
-            let d1 = root.path().join("d1");
-            let f2 = d1.join("f2.txt");
-            let d2 = d1.join("d2");
-            let f3 = d2.join("f3.txt");
+    /// Ignores hidden entries, and the content of hidden directories.
+    ///
+    /// An entry is hidden if its name starts with a dot on Unix, or if it
+    /// has the hidden attribute on Windows. The filter is added to the current
+    /// ones, as with [`and_filter`](Self::and_filter).
+    pub fn filter_hidden(self) -> Self {
+        self.and_filter(|entry| {
+            future::ready(if sys::is_hidden(&entry) {
+                Filtering::IgnoreDir
+            } else {
+                Filtering::Continue
+            })
+        })
+    }
 
-            async_fs::create_dir_all(&d2).await?;
-            async_fs::write(&f1, []).await?;
-            async_fs::write(&f2, []).await?;
-            async_fs::write(&f3, []).await?;
+    /// Only yields the entries whose size in bytes is within `min..=max`.
+    ///
+    /// Directories are always yielded and traversed. The metadata of other
+    /// entries is read asynchronously, without following symbolic links, and
+    /// entries whose metadata cannot be read are ignored. The filter is added
+    /// to the current ones, as with [`and_filter`](Self::and_filter).
+    pub fn filter_size_range(self, min: u64, max: u64) -> Self {
+        self.filter_metadata(false, move |m| (min..=max).contains(&m.len()))
+    }
 
-            let want = vec![f3.to_owned(), f2.to_owned(), f1.to_owned()];
+    /// Only yields the entries of at least `min` bytes.
+    ///
+    /// See [`filter_size_range`](Self::filter_size_range).
+    pub fn filter_min_size(self, min: u64) -> Self {
+        self.filter_size_range(min, u64::MAX)
+    }
 
-            let mut wd = WalkDir::new(root.path()).filter(|entry| async move {
-                match entry.file_type().await {
-                    Ok(ft) if ft.is_dir() => Filtering::Ignore,
-                    _ => Filtering::Continue,
-                }
-            });
+    /// Only yields the entries of at most `max` bytes.
+    ///
+    /// See [`filter_size_range`](Self::filter_size_range).
+    pub fn filter_max_size(self, max: u64) -> Self {
+        self.filter_size_range(0, max)
+    }
 
-            let mut got = Vec::new();
-            while let Some(entry) = wd.next().await {
-                let entry = entry.unwrap();
-                got.push(entry.path());
-            }
-            got.sort();
-            assert_eq!(got, want);
+    /// Only yields the entries modified after `time`.
+    ///
+    /// Directories are always yielded and traversed, whatever their modification
+    /// time. The metadata of other entries is read asynchronously, without
+    /// following symbolic links, and entries whose modification time cannot be
+    /// read are ignored. The filter is added to the current ones, as with
+    /// [`and_filter`](Self::and_filter).
+    pub fn filter_modified_after(self, time: SystemTime) -> Self {
+        self.filter_metadata(false, move |m| m.modified().is_ok_and(|t| t > time))
+    }
 
-            Ok(())
+    /// Only yields the entries modified before `time`.
+    ///
+    /// See [`filter_modified_after`](Self::filter_modified_after).
+    pub fn filter_modified_before(self, time: SystemTime) -> Self {
+        self.filter_metadata(false, move |m| m.modified().is_ok_and(|t| t < time))
+    }
+
+    /// Only yields the entries whose permissions satisfy `predicate`.
+    ///
+    /// Directories not satisfying `predicate` are not yielded but are still
+    /// traversed. The metadata of the entries is read asynchronously, without
+    /// following symbolic links, and entries whose metadata cannot be read are
+    /// ignored. The filter is added to the current ones, as with
+    /// [`and_filter`](Self::and_filter).
+    pub fn with_permissions_filter<F>(self, predicate: F) -> Self
+    where
+        F: Fn(fs::Permissions) -> bool + Send + Sync + 'static,
+    {
+        self.filter_metadata(true, move |m| predicate(m.permissions()))
+    }
+
+    /// Only yields the entries whose mode satisfies `predicate`.
+    ///
+    /// `predicate` receives the full mode, file type bits included. See
+    /// [`with_permissions_filter`](Self::with_permissions_filter).
+    #[cfg(unix)]
+    pub fn with_unix_permissions_filter<F>(self, predicate: F) -> Self
+    where
+        F: Fn(u32) -> bool + Send + Sync + 'static,
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        self.filter_metadata(true, move |m| predicate(m.mode()))
+    }
+
+    /// Only yields the regular files whose mode bits selected by `mask` are
+    /// equal to `expected`.
+    ///
+    /// Other entries are always yielded and directories traversed. The
+    /// metadata of other entries is read asynchronously, as with
+    /// [`filter_size_range`](Self::filter_size_range). The filter is added to
+    /// the current ones, as with [`and_filter`](Self::and_filter).
+    #[cfg(unix)]
+    pub fn with_unix_mode_filter(self, mask: u32, expected: u32) -> Self {
+        use std::os::unix::fs::MetadataExt;
+
+        self.filter_metadata(false, move |m| !m.is_file() || m.mode() & mask == expected)
+    }
+
+    /// Only yields the regular files writable by anyone.
+    ///
+    /// See [`with_unix_mode_filter`](Self::with_unix_mode_filter).
+    #[cfg(unix)]
+    pub fn filter_world_writable(self) -> Self {
+        self.with_unix_mode_filter(0o002, 0o002)
+    }
+
+    /// Only yields the regular files with the set-user-ID bit.
+    ///
+    /// See [`with_unix_mode_filter`](Self::with_unix_mode_filter).
+    #[cfg(unix)]
+    pub fn filter_setuid(self) -> Self {
+        self.with_unix_mode_filter(0o4000, 0o4000)
+    }
+
+    /// Only yields the regular files with the set-group-ID bit.
+    ///
+    /// See [`with_unix_mode_filter`](Self::with_unix_mode_filter).
+    #[cfg(unix)]
+    pub fn filter_setgid(self) -> Self {
+        self.with_unix_mode_filter(0o2000, 0o2000)
+    }
+
+    /// Skips the entries having any of the `attributes` flags, as returned by
+    /// [`MetadataExt::file_attributes`](std::os::windows::fs::MetadataExt::file_attributes).
+    ///
+    /// Matching directories are not traversed. The filter is added to the
+    /// current ones, as with [`and_filter`](Self::and_filter).
+    #[cfg(windows)]
+    pub fn with_windows_attribute_filter(self, attributes: u32) -> Self {
+        use std::os::windows::fs::MetadataExt;
+
+        self.and_filter(move |entry| {
+            let matches = entry
+                .metadata()
+                .is_ok_and(|m| m.file_attributes() & attributes != 0);
+            future::ready(if !matches {
+                Filtering::Continue
+            } else if entry.file_type().is_ok_and(|ft| ft.is_dir()) {
+                Filtering::IgnoreDir
+            } else {
+                Filtering::Ignore
+            })
         })
     }
 
-    #[test]
-    fn filter_dirs_no_traverse() -> Result<()> {
-        block_on(async {
-            let root = tempfile::tempdir()?;
-            let f1 = root.path().join("f1.txt");
-            let d1 = root.path().join("d1");
-            let f2 = d1.join("f2.txt");
-            let d2 = d1.join("d2");
-            let f3 = d2.join("f3.txt");
+    /// Skips the entries having the system attribute.
+    ///
+    /// See [`with_windows_attribute_filter`](Self::with_windows_attribute_filter).
+    #[cfg(windows)]
+    pub fn filter_system_files(self) -> Self {
+        const FILE_ATTRIBUTE_SYSTEM: u32 = 0x4;
+        self.with_windows_attribute_filter(FILE_ATTRIBUTE_SYSTEM)
+    }
 
-            async_fs::create_dir_all(&d2).await?;
-            async_fs::write(&f1, []).await?;
-            async_fs::write(&f2, []).await?;
-            async_fs::write(&f3, []).await?;
+    /// Skips the entries having the archive attribute.
+    ///
+    /// See [`with_windows_attribute_filter`](Self::with_windows_attribute_filter).
+    #[cfg(windows)]
+    pub fn filter_archive_files(self) -> Self {
+        const FILE_ATTRIBUTE_ARCHIVE: u32 = 0x20;
+        self.with_windows_attribute_filter(FILE_ATTRIBUTE_ARCHIVE)
+    }
 
-            let want = vec![d1, f2.to_owned(), f1.to_owned()];
+    /// Skips the entries having the read-only attribute.
+    ///
+    /// See [`with_windows_attribute_filter`](Self::with_windows_attribute_filter).
+    #[cfg(windows)]
+    pub fn filter_read_only_files(self) -> Self {
+        const FILE_ATTRIBUTE_READONLY: u32 = 0x1;
+        self.with_windows_attribute_filter(FILE_ATTRIBUTE_READONLY)
+    }
 
-            let mut wd = WalkDir::new(root.path()).filter(move |entry| {
-                let d2 = d2.clone();
-                async move {
-                    if entry.path() == d2 {
-                        Filtering::IgnoreDir
-                    } else {
-                        Filtering::Continue
+    // Ignores the entries for which `f` returns false, directories included
+    // if `dirs`. Ignored directories are still traversed.
+    fn filter_metadata<F>(self, dirs: bool, f: F) -> Self
+    where
+        F: Fn(&fs::Metadata) -> bool + Send + Sync + 'static,
+    {
+        let f = Arc::new(f);
+        self.and_filter(move |entry| {
+            let f = f.clone();
+            async move {
+                if !dirs && entry.file_type().is_ok_and(|ft| ft.is_dir()) {
+                    return Filtering::Continue;
+                }
+                let keep = match entry.cached_metadata() {
+                    Some(m) => f(m),
+                    None => match async_fs::symlink_metadata(entry.path()).await {
+                        Ok(m) => {
+                            let keep = f(&m);
+                            entry.set_metadata(m);
+                            keep
+                        }
+                        Err(_) => false,
+                    },
+                };
+                if keep {
+                    Filtering::Continue
+                } else {
+                    Filtering::Ignore
+                }
+            }
+        })
+    }
+
+    /// Filter entries with `f` in addition to the current filter.
+    ///
+    /// `f` is only called for the entries the previous filters let
+    /// [`Continue`](Filtering::Continue), thus filters can be chained
+    /// without merging them into a single closure.
+    pub fn and_filter<F, Fut>(mut self, f: F) -> Self
+    where
+        F: FnMut(Arc<Entry>) -> Fut + Send + 'static,
+        Fut: Future<Output = Filtering> + Send + 'static,
+    {
+        let previous = match self.filter.take() {
+            Some(previous) => previous,
+            None => return self.filter(f),
+        };
+        // Filters are called one entry at a time, the lock is never contended.
+        let f = Arc::new(Mutex::new(f));
+        self.filter = Some(Arc::new(Mutex::new(move |entry: Arc<Entry>| {
+            let filtering = call_filter(&previous, entry.clone());
+            let f = f.clone();
+            async move {
+                match filtering.await? {
+                    Filtering::Continue => {
+                        let filtering = f.lock().unwrap_or_else(PoisonError::into_inner)(entry);
+                        Ok(filtering.await)
                     }
+                    filtering => Ok(filtering),
                 }
-            });
+            }
+            .boxed()
+        })));
+        self
+    }
 
-            let mut got = Vec::new();
+    /// Limits the recursion to `depth` levels below the root.
+    ///
+    /// The direct children of the root are at depth 0, thus a `depth` of 0
+    /// yields the root children without descending into any subdirectory.
+    pub fn with_max_depth(mut self, depth: usize) -> Self {
+        self.options.max_depth = Some(depth);
+        self
+    }
+
+    /// Skips entries less than `depth` levels below the root.
+    ///
+    /// Shallower directories are still traversed so that their deeper children
+    /// are reached, but they are not returned. The direct children of the root
+    /// are at depth 0.
+    pub fn with_min_depth(mut self, depth: usize) -> Self {
+        self.options.min_depth = depth;
+        self
+    }
+
+    /// Follows symbolic links pointing to directories.
+    ///
+    /// When enabled, a link leading back to one of its own ancestors is reported
+    /// as an error instead of being traversed forever.
+    pub fn with_follow_links(mut self, follow: bool) -> Self {
+        self.options.follow_links = follow;
+        self
+    }
+
+    /// Resolves the roots which are symbolic links before walking them,
+    /// whether or not links are followed during the walk.
+    ///
+    /// The entries of such a root are then returned with paths below its
+    /// canonical path. Otherwise, their paths are below the link itself,
+    /// which is read through anyway. Disabled by default.
+    pub fn with_follow_root_links(mut self, follow: bool) -> Self {
+        self.options.follow_root_links = follow;
+        self
+    }
+
+    /// Limits to `n` the length of the chains of symbolic links followed,
+    /// when following links.
+    ///
+    /// A link pointing to another link, and so on, more than `n` times is
+    /// returned as an error instead of being resolved by the system. Defaults
+    /// to 40, as Linux.
+    pub fn with_symlink_limit(mut self, n: usize) -> Self {
+        self.options.symlink_limit = Some(n);
+        self
+    }
+
+    /// Reports directories visited more than once.
+    ///
+    /// Directories are identified by their device and inode numbers (volume
+    /// serial number and file index on Windows), so that a directory reached
+    /// again through a hard link, a bind mount or a followed symbolic link
+    /// produces an error instead of being traversed twice. This is
+    /// independent of [`with_follow_links`](Self::with_follow_links).
+    pub fn with_detect_cycles(mut self, detect: bool) -> Self {
+        self.options.detect_cycles = detect;
+        self
+    }
+
+    /// Does not cross file system boundaries.
+    ///
+    /// Directories living on a different device than the root (a different
+    /// volume on Windows) are ignored along with their content, whether
+    /// symbolic links are followed or not.
+    pub fn with_same_file_system(mut self, same: bool) -> Self {
+        self.options.same_file_system = same;
+        self
+    }
+
+    /// Returns a stream yielding the root directory itself before its
+    /// content, as a [`RootedEntry::Root`].
+    ///
+    /// The root has no [`std::fs::DirEntry`], see [`RootEntry`]. It is not
+    /// filtered, is yielded last in contents first mode, and the entry hooks
+    /// are not called on it. Its content is yielded as [`RootedEntry::Entry`],
+    /// the roots being yielded only if `root_entry` is set.
+    pub fn with_root_entry(mut self, root_entry: bool) -> RootedWalkDir {
+        self.root_entry = root_entry;
+        RootedWalkDir::new(self)
+    }
+
+    /// Yields the content of a directory before the directory itself.
+    ///
+    /// This gives a post-order traversal, useful to process a tree bottom-up,
+    /// as for a recursive deletion. Only the entries of the directories being
+    /// traversed are retained, not the whole tree.
+    pub fn with_contents_first(mut self, contents_first: bool) -> Self {
+        self.options.contents_first = contents_first;
+        self
+    }
+
+    /// Limits the number of directories kept open at once to `n`.
+    ///
+    /// Once the limit is reached, the remaining entries of the oldest open
+    /// directory are read in memory so that its handle can be closed. This
+    /// bounds the number of file descriptors used to walk deep trees, at the
+    /// expense of memory. A limit of 0 is treated as 1.
+    pub fn with_max_open(mut self, n: usize) -> Self {
+        self.options.max_open = Some(n);
+        self
+    }
+
+    /// Reads the directories on the thread pool by batches of `n` entries,
+    /// the next batch of the current directory being read ahead.
+    ///
+    /// By default the entries are read one at a time as the stream is polled,
+    /// without any buffering but on the polling task. Batches move these
+    /// blocking reads to the thread pool while keeping at most two batches per
+    /// open directory in memory, so that a slow consumer does not make the
+    /// walk buffer whole directories. It has no effect when sorting or in
+    /// [`with_parallelism`](Self::with_parallelism) mode, where directories
+    /// are read as a whole. A value of 0 is treated as 1.
+    pub fn with_buffer_size(mut self, n: usize) -> Self {
+        self.options.buffer_size = Some(n);
+        self
+    }
+
+    /// Opens the directories with `f` instead of [`read_dir`].
+    ///
+    /// `f` is called for every directory traversed, on the thread pool in
+    /// [`with_parallelism`](Self::with_parallelism) mode. As a `ReadDir` can
+    /// only be obtained from [`read_dir`], this is meant to redirect,
+    /// instrument or fail the reads, to test error handling for instance,
+    /// rather than to walk a virtual file system.
+    pub fn with_open_dir_fn(mut self, f: OpenDirFn) -> Self {
+        self.open_dir = Some(f);
+        self
+    }
+
+    /// Traverses the tree breadth first.
+    ///
+    /// All the entries of a directory are returned before entering any of its
+    /// subdirectories, which are queued as they are found. Only one directory
+    /// is open at a time, but the paths of the queued directories are kept in
+    /// memory. [`with_contents_first`](Self::with_contents_first) has no effect
+    /// in this mode.
+    ///
+    /// Directories being entered in the order they are found, the entries
+    /// of a root are returned level by level. See
+    /// [`with_ordered_by_depth`](Self::with_ordered_by_depth) to keep this
+    /// order along with [`with_parallelism`](Self::with_parallelism).
+    pub fn with_breadth_first(mut self, breadth_first: bool) -> Self {
+        self.options.breadth_first = breadth_first;
+        self
+    }
+
+    /// Returns all the entries of a root at a given depth before any entry
+    /// deeper.
+    ///
+    /// The tree is traversed breadth first, see
+    /// [`with_breadth_first`](Self::with_breadth_first), and in
+    /// [`with_parallelism`](Self::with_parallelism) mode the directories of a
+    /// level are only read once the previous level is fully read. The order of
+    /// the entries of a level is unspecified unless sorted and not parallel.
+    /// The roots are walked one after the other.
+    pub fn with_ordered_by_depth(mut self, ordered: bool) -> Self {
+        self.options.ordered_by_depth = ordered;
+        self
+    }
+
+    /// Reads up to `n` directories concurrently on a thread pool.
+    ///
+    /// Directories are read as a whole and their entries are yielded in the
+    /// order the reads complete, so that entries are no longer grouped by
+    /// parent. As in [`with_breadth_first`](Self::with_breadth_first) mode,
+    /// [`with_contents_first`](Self::with_contents_first) and
+    /// [`with_max_open`](Self::with_max_open) have no effect. The roots are
+    /// still read one after the other.
+    ///
+    /// A value of 0 is treated as 1.
+    pub fn with_parallelism(mut self, n: usize) -> Self {
+        self.options.parallelism = Some(n);
+        self
+    }
+
+    /// Skips the files already walked under another path, such as hard links.
+    ///
+    /// Files are identified by their device and inode numbers, or their
+    /// volume serial number and file index on Windows, which are kept for the
+    /// whole walk. Directories are not deduplicated, see
+    /// [`with_detect_cycles`](Self::with_detect_cycles), and symbolic links
+    /// are distinct entries unless followed. Files whose identifier cannot be
+    /// read are not deduplicated.
+    ///
+    /// Disabled by default.
+    pub fn with_deduplicate(mut self, deduplicate: bool) -> Self {
+        self.options.deduplicate = deduplicate;
+        self
+    }
+
+    /// Ends the walk once `n` errors have been yielded.
+    ///
+    /// The `n`-th error is yielded and the stream ends right after it. A value
+    /// of 0, the default, never ends the walk on errors.
+    pub fn with_error_limit(mut self, n: usize) -> Self {
+        self.options.error_limit = n;
+        self
+    }
+
+    /// Skips the remaining entries of a directory once `n` errors have been
+    /// met walking its entries.
+    ///
+    /// Errors reading the directory and errors on its entries, such as a
+    /// subdirectory that cannot be opened, are counted. The `n`-th error is
+    /// replaced by a single error for the directory, of the same kind, so that
+    /// a failing directory on a network file system does not yield an error
+    /// per entry. A value of 0 is treated as 1.
+    pub fn with_max_io_errors_per_dir(mut self, n: usize) -> Self {
+        self.options.max_io_errors_per_dir = Some(n);
+        self
+    }
+
+    /// Ends the walk once `n` entries have been yielded.
+    ///
+    /// Unlike [`StreamExt::take`], no directory is read any further once the
+    /// limit is reached. Errors are not counted.
+    pub fn with_max_entries(mut self, n: u64) -> Self {
+        self.options.max_entries = Some(n);
+        self
+    }
+
+    /// Yields at most `n` entries of each directory.
+    ///
+    /// Once `n` entries of a directory have been yielded, the directory is
+    /// no longer read and the walk goes on with the next one, so that huge
+    /// directories are not read entirely. Filtered entries are not counted.
+    pub fn with_entry_limit_per_dir(mut self, n: usize) -> Self {
+        self.options.entry_limit_per_dir = Some(n);
+        self
+    }
+
+    /// Skips the regular files larger than `bytes`.
+    ///
+    /// Other entries, directories included, are not affected. The metadata of
+    /// regular files is read before filtering them, unless already prefetched
+    /// with [`with_prefetch_metadata`](Self::with_prefetch_metadata), and is
+    /// attached to the yielded entries. Files whose metadata cannot be read
+    /// are skipped.
+    pub fn with_max_file_size(mut self, bytes: u64) -> Self {
+        self.options.max_file_size = Some(bytes);
+        self
+    }
+
+    /// Skips the regular files smaller than `bytes`.
+    ///
+    /// Combined with [`with_max_file_size`](Self::with_max_file_size), only
+    /// the regular files within both bounds are yielded. See
+    /// [`with_max_file_size`](Self::with_max_file_size) for the other details.
+    pub fn with_min_file_size(mut self, bytes: u64) -> Self {
+        self.options.min_file_size = Some(bytes);
+        self
+    }
+
+    /// Reads the metadata of every entry before filtering it.
+    ///
+    /// The metadata is attached to the entry and available through
+    /// [`Entry::cached_metadata`], for filters and consumers of the
+    /// stream, as well as to the built-in size and time filters. Entries whose
+    /// metadata cannot be read are processed without any.
+    ///
+    /// Disabled by default.
+    pub fn with_prefetch_metadata(mut self, prefetch: bool) -> Self {
+        self.options.prefetch_metadata = prefetch;
+        self
+    }
+
+    /// Skips the entries matching the `.gitignore` files of the walked
+    /// directories.
+    ///
+    /// The `.gitignore` file of each directory is read when entering it and
+    /// applies to all of its descendants, rules of nested files taking
+    /// precedence over the ones of their parents. Ignored directories are not
+    /// traversed. Invalid patterns are skipped.
+    ///
+    /// Disabled by default. Requires the `ignore` feature.
+    #[cfg(feature = "ignore")]
+    pub fn with_gitignore(mut self, gitignore: bool) -> Self {
+        self.options.gitignore = gitignore;
+        self
+    }
+
+    /// Skips the entries matching the `.ignore` files of the walked
+    /// directories.
+    ///
+    /// `.ignore` files use the `.gitignore` syntax and are applied the same
+    /// way, see [`with_gitignore`](Self::with_gitignore). When both are
+    /// enabled, rules of a `.ignore` file take precedence over the ones of the
+    /// `.gitignore` file of the same directory.
+    ///
+    /// Disabled by default. Requires the `ignore` feature.
+    #[cfg(feature = "ignore")]
+    pub fn with_ignore_files(mut self, ignore_files: bool) -> Self {
+        self.options.ignore_files = ignore_files;
+        self
+    }
+
+    /// Skips the entries matching the ignore files named `filename` in the
+    /// walked directories, such as `.dockerignore`.
+    ///
+    /// The files use the `.gitignore` syntax and are applied the same way, see
+    /// [`with_gitignore`](Self::with_gitignore). Each call adds a file name to
+    /// read, rules of the file names added last taking precedence. Custom
+    /// ignore files take precedence over `.gitignore` and `.ignore` files.
+    ///
+    /// Requires the `ignore` feature.
+    #[cfg(feature = "ignore")]
+    pub fn with_custom_ignore_file(mut self, filename: &str) -> Self {
+        self.options.custom_ignore_files.push(filename.to_owned());
+        self
+    }
+
+    /// Skips `path`, which is neither yielded nor entered.
+    ///
+    /// A relative `path` is relative to the roots, an absolute one is compared
+    /// with the entry paths as walked, thus should be used with absolute
+    /// roots. Paths are compared component by component, without resolving
+    /// links or `..`. Calls accumulate.
+    pub fn exclude_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.options.excluded_paths.insert(path.as_ref().to_owned());
+        self
+    }
+
+    /// Only yields the entries within the subtrees at `paths`.
+    ///
+    /// The directories leading to these subtrees are traversed but not
+    /// yielded, the other entries are skipped. Paths are resolved as by
+    /// [`exclude_path`](Self::exclude_path). An empty list yields nothing.
+    pub fn with_included_paths(mut self, paths: Vec<PathBuf>) -> Self {
+        self.options.included_paths = Some(paths);
+        self
+    }
+
+    /// Sorts the entries of each directory with `cmp`.
+    ///
+    /// The entries of a directory are read in memory and sorted before being
+    /// processed, thus memory usage is bounded by the largest directory, not
+    /// by the whole tree. Entries are still returned depth first, or breadth
+    /// first with [`with_breadth_first`](Self::with_breadth_first).
+    pub fn with_sort_by<F>(mut self, cmp: F) -> Self
+    where
+        F: Fn(&DirEntry, &DirEntry) -> cmp::Ordering + Send + Sync + 'static,
+    {
+        self.sort = Some(Sort::By(Arc::new(cmp)));
+        self
+    }
+
+    /// Sorts the entries of each directory by file name.
+    ///
+    /// See [`with_sort_by`](Self::with_sort_by).
+    pub fn with_sort_by_name(self) -> Self {
+        self.with_sort_by(|a, b| a.file_name().cmp(&b.file_name()))
+    }
+
+    /// Sorts the entries of each directory by file name, ignoring case.
+    ///
+    /// See [`with_sort_by`](Self::with_sort_by).
+    pub fn with_sort_by_name_case_insensitive(self) -> Self {
+        self.with_sort_by(|a, b| {
+            let a = a.file_name().to_string_lossy().to_lowercase();
+            let b = b.file_name().to_string_lossy().to_lowercase();
+            a.cmp(&b)
+        })
+    }
+
+    /// Sorts the entries of each directory by file name, comparing the
+    /// sequences of digits by numeric value, so that `file2` comes before
+    /// `file10`.
+    ///
+    /// Names with equal numbers, such as `file01` and `file1`, are ordered
+    /// lexicographically. See [`with_sort_by`](Self::with_sort_by).
+    pub fn with_sort_natural(self) -> Self {
+        self.with_sort_by(|a, b| {
+            natural_cmp(
+                &a.file_name().to_string_lossy(),
+                &b.file_name().to_string_lossy(),
+            )
+        })
+    }
+
+    /// Sorts the entries of each directory by modification time, newest first.
+    ///
+    /// The metadata of the entries of a directory is read on a thread pool,
+    /// with a bounded number of concurrent tasks. Entries whose modification
+    /// time cannot be read are replaced by an error, yielded after the other
+    /// entries of the directory. See [`with_sort_by`](Self::with_sort_by).
+    pub fn with_sort_by_modified_time(mut self) -> Self {
+        self.sort = Some(Sort::ModifiedTime);
+        self
+    }
+
+    /// Yields the entries of each directory in the reverse of the order they
+    /// are read from the file system.
+    ///
+    /// This is cheaper than sorting when the order of the file system is
+    /// meaningful, newest first for instance. The entries of a directory are
+    /// read in memory as when sorting, and this replaces any sort. See
+    /// [`with_sort_by`](Self::with_sort_by).
+    pub fn with_reversed_fs_order(mut self) -> Self {
+        self.sort = Some(Sort::Reversed);
+        self
+    }
+
+    /// Collects statistics about the walk.
+    ///
+    /// Returns the walker along with the statistics, which are updated as the
+    /// stream is polled. Reading the size of the regular files costs a call to
+    /// [`metadata`](std::fs::DirEntry::metadata) per file.
+    pub fn with_stats(mut self) -> (Self, Arc<WalkDirStats>) {
+        let stats = Arc::new(WalkDirStats::default());
+        self.stats = Some(stats.clone());
+        (self, stats)
+    }
+
+    /// Broadcasts the progress of the walk on a Tokio watch channel.
+    ///
+    /// Returns the walker along with the receiver, which is updated each time
+    /// an entry is yielded, so that another task can display the progress.
+    #[cfg(feature = "tokio")]
+    pub fn with_progress_watch(mut self) -> (Self, tokio::sync::watch::Receiver<WalkProgress>) {
+        let (sender, receiver) = tokio::sync::watch::channel(WalkProgress::default());
+        self.progress = Some(sender);
+        (self, receiver)
+    }
+
+    /// Saves a checkpoint when the walk is interrupted by a transient error.
+    ///
+    /// Errors reading a directory are deemed transient, as network failures,
+    /// unless tied to the file system content, such as a missing entry or a
+    /// denied permission. On the first of them, the state of the walk is saved
+    /// and the stream ends right after yielding the error. The walk can then
+    /// be resumed with [`resume_from`](Self::resume_from) and the checkpoint
+    /// returned by [`into_checkpoint`](Self::into_checkpoint).
+    pub fn with_checkpoint(mut self) -> Self {
+        self.checkpoint = Some(CheckpointSlot::default());
+        self
+    }
+
+    /// Returns the checkpoint saved when the walk was interrupted, if any.
+    ///
+    /// See [`with_checkpoint`](Self::with_checkpoint).
+    pub fn into_checkpoint(self) -> Option<WalkCheckpoint> {
+        let checkpoint = self.checkpoint?;
+        let mut checkpoint = checkpoint.lock().unwrap_or_else(PoisonError::into_inner);
+        checkpoint.take()
+    }
+
+    /// Returns a new `WalkDir` resuming the walk interrupted at `checkpoint`.
+    ///
+    /// The directories being traversed when the walk was interrupted are read
+    /// again, skipping the entries already walked. The configuration of the
+    /// interrupted walk is not saved and should be set again. The ignore files
+    /// of the parents of the directories being traversed are not read again.
+    pub fn resume_from(mut checkpoint: WalkCheckpoint) -> Self {
+        let mut wd = Self::new_multi(std::mem::take(&mut checkpoint.roots));
+        wd.resume = Some(checkpoint);
+        wd
+    }
+
+    /// Returns a new `WalkDir` continuing the walk from its current state, if
+    /// it can be duplicated.
+    ///
+    /// Before the stream is polled, the clone has the same roots and
+    /// configuration. Afterwards both walks yield the remaining entries on
+    /// their own, the clone reading again the directories being traversed on
+    /// the thread pool and skipping the entries removed meanwhile. The
+    /// remaining entries of these directories are read in memory for `self`
+    /// first, which blocks the task until the batches being read complete.
+    ///
+    /// The filters, the sort and the cancellation token are shared with the
+    /// clone, a filter being called by both walks. The statistics, the
+    /// progress watch and the hooks are not carried over to the clone, which
+    /// times out on its own. Returns `None` while an entry is being read, and
+    /// once the walk is combined with [`chain`](Self::chain) or
+    /// [`interleave`](Self::interleave) or over.
+    pub fn try_clone(&mut self) -> Option<Self> {
+        let checkpoint = self.checkpoint.as_ref().map(|_| CheckpointSlot::default());
+        let entries = match &mut self.entries {
+            None => None,
+            Some(Walking::Roots(walk)) => {
+                let walker = walk.walker.as_mut()?.try_clone(checkpoint.clone());
+                Some(Walking::Roots(WalkerStream::new(walker)))
+            }
+            Some(Walking::Boxed(_)) => return None,
+        };
+        Some(Self {
+            roots: self.roots.clone(),
+            root_read_dir: None,
+            options: self.options.clone(),
+            filter: self.filter.clone(),
+            sort: self.sort.clone(),
+            stats: None,
+            rate_limit: self.rate_limit.clone(),
+            open_dir: self.open_dir.clone(),
+            checkpoint,
+            resume: self.resume.clone(),
+            cancellation: self.cancellation.clone(),
+            timeout: self.timeout.clone(),
+            deadline: self.deadline.as_ref().map(|deadline| Deadline {
+                duration: deadline.duration,
+                sleep: deadline.sleep.clone(),
+                timer: None,
+                root: deadline.root.clone(),
+            }),
+            hooks: Vec::new(),
+            dir_enter_hooks: Vec::new(),
+            dir_exit_hooks: Vec::new(),
+            #[cfg(feature = "tokio")]
+            progress: None,
+            entries,
+            terminated: false,
+            root_entry: self.root_entry,
+        })
+    }
+
+    /// Calls `f` on each entry right before it is yielded, after filtering.
+    ///
+    /// Hooks observe the entries, to log them or update a counter, without
+    /// affecting the walk. They are called in the order they are added.
+    pub fn with_entry_hook<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&Entry) + Send + 'static,
+    {
+        self.hooks.push(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the path of each directory whose entries are about to
+    /// be walked, roots included.
+    ///
+    /// See [`with_dir_exit_hook`](Self::with_dir_exit_hook) to be notified
+    /// once a directory is done. Hooks are called in the order they are added.
+    pub fn with_dir_enter_hook<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&Path) + Send + 'static,
+    {
+        self.dir_enter_hooks.push(Box::new(f));
+        self
+    }
+
+    /// Calls `f` with the path of each directory once its last entry was
+    /// yielded, roots included.
+    ///
+    /// In [`with_contents_first`](Self::with_contents_first) mode, `f` is
+    /// called before the entry of the directory itself is yielded. The
+    /// directories left when the walk is stopped early, by a filter or a
+    /// limit, are not reported. Hooks are called in the order they are added.
+    pub fn with_dir_exit_hook<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(&Path) + Send + 'static,
+    {
+        self.dir_exit_hooks.push(Box::new(f));
+        self
+    }
+
+    /// Ends the walk once `token` is cancelled.
+    ///
+    /// The token is checked each time the stream is polled, which then ends
+    /// without yielding any error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::atomic::{AtomicBool, Ordering};
+    /// use std::sync::Arc;
+    ///
+    /// use async_walkdir::WalkDir;
+    ///
+    /// let cancelled = Arc::new(AtomicBool::new(false));
+    /// let entries = WalkDir::new("my_directory").with_cancellation_token(cancelled.clone());
+    ///
+    /// // On shutdown:
+    /// cancelled.store(true, Ordering::Relaxed);
+    /// ```
+    pub fn with_cancellation_token(mut self, token: impl CancellationToken) -> Self {
+        self.cancellation = Some(Arc::new(token));
+        self
+    }
+
+    /// Processes at most `entries_per_sec` entries per second on average,
+    /// to reduce the load on shared file systems.
+    ///
+    /// Short bursts of up to one second worth of entries are allowed. When
+    /// the limit is reached, the walk awaits the future returned by `sleep`
+    /// for the delay to wait, the timer of the runtime such as
+    /// `tokio::time::sleep` or `async_io::Timer::after`, as this crate is
+    /// runtime agnostic. Filtered entries are counted. A rate which is not
+    /// positive disables the limit.
+    pub fn with_rate_limit<F, Fut>(mut self, entries_per_sec: f64, sleep: F) -> Self
+    where
+        F: Fn(Duration) -> Fut + Send + Sync + 'static,
+        Fut: Future + Send + 'static,
+    {
+        self.rate_limit = Some((entries_per_sec, sleep_fn(sleep))).filter(|(rate, _)| *rate > 0.0);
+        self
+    }
+
+    /// Ends the walk once `duration` has elapsed since the stream was first
+    /// polled.
+    ///
+    /// The walk is raced against the future returned by `sleep(duration)`,
+    /// the timer of the runtime as for
+    /// [`with_rate_limit`](Self::with_rate_limit), created when the stream is
+    /// first polled and dropped with the walk. Once it completes, a
+    /// [`TimedOut`](io::ErrorKind::TimedOut) error with the first root as path
+    /// is yielded, and the stream ends. A directory read on the polling task
+    /// cannot be interrupted: use [`with_buffer_size`](Self::with_buffer_size)
+    /// or [`with_parallelism`](Self::with_parallelism) so that the walk can
+    /// time out while a read hangs, on a network file system for instance.
+    pub fn with_timeout<F, Fut>(mut self, duration: Duration, sleep: F) -> Self
+    where
+        F: Fn(Duration) -> Fut + Send + Sync + 'static,
+        Fut: Future + Send + 'static,
+    {
+        self.timeout = Some((duration, sleep_fn(sleep)));
+        self
+    }
+
+    /// Returns a stream of the entries whose paths are rewritten by `f`, for
+    /// instance to strip a common prefix.
+    ///
+    /// `f` receives the original path of each entry. Errors keep the original
+    /// path.
+    pub fn with_path_transform<F>(
+        self,
+        mut f: F,
+    ) -> impl Stream<Item = Result<TransformedEntry>> + Send + Unpin
+    where
+        F: FnMut(&Path) -> PathBuf + Send + 'static,
+    {
+        self.map(move |entry| {
+            entry.map(|entry| TransformedEntry {
+                path: f(&entry.path()),
+                entry,
+            })
+        })
+        .boxed()
+    }
+
+    /// Returns a stream of the entry paths.
+    pub fn into_path_stream(self) -> impl Stream<Item = Result<PathBuf>> + Send + Unpin {
+        self.map(|entry| entry.map(|entry| entry.path()))
+    }
+
+    /// Returns a stream of the directory entries only.
+    ///
+    /// Symbolic links are only yielded if they are followed, see
+    /// [`with_follow_links`](Self::with_follow_links), and point to a directory.
+    pub fn into_dir_stream(self) -> impl Stream<Item = Result<Entry>> + Send + Unpin {
+        self.filter_file_type(fs::FileType::is_dir)
+    }
+
+    /// Returns a stream of the regular file entries only.
+    ///
+    /// Symbolic links are only yielded if they are followed, see
+    /// [`with_follow_links`](Self::with_follow_links), and point to a regular file.
+    pub fn into_file_stream(self) -> impl Stream<Item = Result<Entry>> + Send + Unpin {
+        self.filter_file_type(fs::FileType::is_file)
+    }
+
+    // Ignores the entries whose file type does not satisfy `f`, following links if requested.
+    fn filter_file_type(self, f: fn(&fs::FileType) -> bool) -> Self {
+        let follow_links = self.options.follow_links;
+        self.and_filter(move |entry| async move {
+            let file_type = match entry.file_type() {
+                Ok(ft) if ft.is_symlink() && follow_links => {
+                    let path = entry.path();
+                    blocking::unblock(move || fs::metadata(path))
+                        .await
+                        .map(|m| m.file_type())
+                }
+                file_type => file_type,
+            };
+            if file_type.is_ok_and(|ft| f(&ft)) {
+                Filtering::Continue
+            } else {
+                Filtering::Ignore
+            }
+        })
+    }
+
+    /// Returns a stream of the entries, silently dropping errors.
+    pub fn skip_errors(self) -> impl Stream<Item = Entry> + Send + Unpin {
+        self.filter_map(|entry| entry.ok())
+    }
+
+    /// Returns a stream of the entries, giving errors to `f` instead of yielding them.
+    ///
+    /// `f` is awaited before the walk goes on, and the error is dropped afterwards.
+    pub fn on_error<F, Fut>(self, f: F) -> impl Stream<Item = Entry> + Send + Unpin
+    where
+        F: FnMut(WalkDirError) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        stream::unfold((self, f), |(mut entries, mut f)| async move {
+            loop {
+                match entries.next().await? {
+                    Ok(entry) => return Some((entry, (entries, f))),
+                    Err(e) => f(e).await,
+                }
+            }
+        })
+        .boxed()
+    }
+
+    /// Returns a stream of the items of the streams `f` resolves to for each
+    /// item of the walk.
+    ///
+    /// This expands each entry into any number of items, for instance the
+    /// lines of each file. The stream returned for an item is exhausted before
+    /// the walk goes on.
+    pub fn flat_map_async<F, Fut, S>(self, f: F) -> impl Stream<Item = S::Item> + Send + Unpin
+    where
+        F: FnMut(Result<Entry>) -> Fut + Send + 'static,
+        Fut: Future<Output = S> + Send + 'static,
+        S: Stream + Send + 'static,
+    {
+        self.then(f).flatten().boxed()
+    }
+
+    // Starts the walk, returning its entries.
+    fn start(&mut self) -> Walking {
+        if let Some((duration, sleep)) = self.timeout.clone() {
+            self.deadline = Some(Deadline {
+                duration,
+                sleep,
+                timer: None,
+                root: self.roots.first().cloned().unwrap_or_default(),
+            });
+        }
+        Walking::Roots(walk_dir(self))
+    }
+
+    /// Returns a `WalkDir` yielding the entries of `self`, then the ones of
+    /// `other` once `self` is over.
+    ///
+    /// Each walk keeps its own configuration, which should be set before
+    /// chaining: the options set afterwards are ignored. The cancellation
+    /// token and the statistics of `self` cover both walks.
+    ///
+    /// This shadows [`StreamExt::chain`], which can still be called as
+    /// `StreamExt::chain(walk, stream)` to chain another kind of stream.
+    pub fn chain(mut self, other: WalkDir) -> Self {
+        let entries = self.entries.take().unwrap_or_else(|| self.start());
+        let other = other.map(|item| item.map(RootedEntry::Entry));
+        self.entries = Some(Walking::Boxed(entries.chain(other).boxed()));
+        self
+    }
+
+    /// Returns a stream whose next item can be inspected with
+    /// [`PeekableWalkDir::peek`] before it is yielded.
+    pub fn peekable(self) -> PeekableWalkDir {
+        PeekableWalkDir::new(self)
+    }
+
+    /// Returns a `WalkDir` yielding the entries of `self` and `other` in
+    /// turn, one item of each, starting with `self`.
+    ///
+    /// Once one of the walks is over, the remaining items of the other are
+    /// yielded. The configuration is handled as by [`chain`](Self::chain).
+    pub fn interleave(mut self, other: WalkDir) -> Self {
+        let entries = self.entries.take().unwrap_or_else(|| self.start());
+        let other = other.map(|item| item.map(RootedEntry::Entry));
+        let walks = (entries.boxed(), Some(other.boxed()));
+        let entries = stream::unfold(walks, |(mut next, mut after)| async move {
+            match next.next().await {
+                Some(item) => match after.take() {
+                    Some(after) => Some((item, (after, Some(next)))),
+                    None => Some((item, (next, None))),
+                },
+                None => {
+                    let mut after = after?;
+                    let item = after.next().await?;
+                    Some((item, (after, None)))
+                }
+            }
+        });
+        self.entries = Some(Walking::Boxed(entries.boxed()));
+        self
+    }
+
+    /// Walks the tree on the thread pool and sends the items to the returned
+    /// channel, holding at most `capacity` items.
+    ///
+    /// The walk runs until it is over or until the receiver is dropped,
+    /// whatever the async runtime, but it occupies a thread of the pool used
+    /// for blocking IOs meanwhile. A capacity of 0 is treated as 1.
+    pub fn into_channel(mut self, capacity: usize) -> async_channel::Receiver<Result<Entry>> {
+        let (sender, receiver) = async_channel::bounded(capacity.max(1));
+        blocking::unblock(move || {
+            future::block_on(async move {
+                while let Some(item) = self.next().await {
+                    if sender.send(item).await.is_err() {
+                        break;
+                    }
+                }
+            })
+        })
+        .detach();
+        receiver
+    }
+
+    /// Walks the whole tree, running `f` on each item with at most `limit`
+    /// calls in progress at once.
+    ///
+    /// The futures returned by `f` are polled concurrently on the current task
+    /// while the walk goes on, which suits IO bound processing such as hashing
+    /// files with an async file API. A `limit` of 0 is treated as 1.
+    pub async fn for_each_concurrent<F, Fut>(mut self, limit: usize, mut f: F)
+    where
+        F: FnMut(Result<Entry>) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let limit = limit.max(1);
+        let mut running: Vec<Pin<Box<Fut>>> = Vec::new();
+        let mut exhausted = false;
+        future::poll_fn(|cx| loop {
+            while !exhausted && running.len() < limit {
+                match Pin::new(&mut self).poll_next(cx) {
+                    Poll::Ready(Some(item)) => running.push(Box::pin(f(item))),
+                    Poll::Ready(None) => exhausted = true,
+                    Poll::Pending => break,
+                }
+            }
+            let count = running.len();
+            running.retain_mut(|fut| fut.as_mut().poll(cx).is_pending());
+            if exhausted && running.is_empty() {
+                return Poll::Ready(());
+            }
+            if running.len() == count {
+                return Poll::Pending;
+            }
+        })
+        .await
+    }
+
+    /// Walks the whole tree, running `f` on each entry in turn.
+    ///
+    /// Stops at the first error, from the walk or from `f`, which is returned.
+    /// The errors of `f` are returned with the path of the entry.
+    pub async fn try_for_each<F, Fut>(mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(Arc<Entry>) -> Fut,
+        Fut: Future<Output = io::Result<()>>,
+    {
+        while let Some(entry) = self.next().await {
+            let entry = Arc::new(entry?);
+            if let Err(e) = f(entry.clone()).await {
+                return Err(WalkDirError::new(entry.path(), e));
+            }
+        }
+        Ok(())
+    }
+
+    /// Walks the whole tree and returns the path of every entry.
+    ///
+    /// Stops at the first error, which is returned.
+    pub async fn collect_paths(mut self) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        while let Some(entry) = self.next().await {
+            paths.push(entry?.path());
+        }
+        Ok(paths)
+    }
+
+    /// Walks the tree until `n` entries are found and returns them, in walk
+    /// order.
+    ///
+    /// Fewer entries are returned if the tree has less. Stops at the first
+    /// error, which is returned.
+    pub async fn first_n(self, n: usize) -> Result<Vec<Entry>> {
+        self.take(n).try_collect().await
+    }
+
+    /// Walks the whole tree and returns the number of entries.
+    ///
+    /// Stops at the first error, which is returned.
+    pub async fn count(mut self) -> Result<u64> {
+        self.try_fold(0, |count, _| Ok(count + 1)).await
+    }
+
+    /// Walks the tree until `predicate` returns `true` and returns the
+    /// matching entry, or `None` if no entry matches.
+    ///
+    /// The walk stops at the first match or at the first error, which is
+    /// returned. As for [`filter`](Self::filter), the entry is read again if
+    /// `predicate` retained it.
+    pub async fn find_first<F, Fut>(mut self, mut predicate: F) -> Result<Option<Entry>>
+    where
+        F: FnMut(Arc<Entry>) -> Fut,
+        Fut: Future<Output = bool>,
+    {
+        let open_dir = self
+            .open_dir
+            .clone()
+            .unwrap_or_else(|| Arc::new(|path: &Path| read_dir(path)));
+        while let Some(entry) = self.next().await {
+            let entry = Arc::new(entry?);
+            if predicate(entry.clone()).await {
+                return unshare(entry, &open_dir).await.map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Walks the whole tree and groups the regular files having the same
+    /// content, as hashed by `hasher`, with at most `limit` hashes in progress
+    /// at once.
+    ///
+    /// `hasher` receives the path of each regular file and returns the hash
+    /// of its content. Only the groups of more than one file are returned,
+    /// keyed by hash, and the order of the paths in a group is unspecified.
+    /// Symbolic links are not hashed. A `limit` of 0 is treated as 1.
+    ///
+    /// Stops at the first error, from the walk or from `hasher`, which is
+    /// returned.
+    pub async fn find_duplicates<F, Fut>(
+        self,
+        limit: usize,
+        mut hasher: F,
+    ) -> Result<HashMap<Vec<u8>, Vec<PathBuf>>>
+    where
+        F: FnMut(PathBuf) -> Fut,
+        Fut: Future<Output = io::Result<Vec<u8>>>,
+    {
+        let mut groups: HashMap<Vec<u8>, Vec<PathBuf>> = HashMap::new();
+        self.map_files_concurrent(
+            limit,
+            |entry| {
+                let path = entry.path();
+                let hash = hasher(path.clone());
+                async move {
+                    match hash.await {
+                        Ok(hash) => Ok((hash, path)),
+                        Err(e) => Err(WalkDirError::new(path, e)),
+                    }
+                }
+            },
+            |(hash, path)| groups.entry(hash).or_default().push(path),
+        )
+        .await?;
+        groups.retain(|_, paths| paths.len() > 1);
+        Ok(groups)
+    }
+
+    /// Walks the whole tree and returns the `n` most recently modified
+    /// regular files, newest first.
+    ///
+    /// The modification times are read on the thread pool while the walk
+    /// goes on, and only `n` entries are kept in memory. Symbolic links are
+    /// ignored.
+    ///
+    /// Stops at the first error, including an error reading a modification
+    /// time, which is returned.
+    pub async fn last_n_by_modified_time(self, n: usize) -> Result<Vec<Entry>> {
+        let mut newest = BinaryHeap::new();
+        if n == 0 {
+            return Ok(Vec::new());
+        }
+        self.map_files_concurrent(
+            METADATA_CONCURRENCY,
+            |entry| async move {
+                match entry.async_metadata().await.and_then(|m| m.modified()) {
+                    Ok(modified) => Ok(ByModifiedTime(modified, entry)),
+                    Err(e) => Err(WalkDirError::new(entry.path(), e)),
+                }
+            },
+            |entry| {
+                newest.push(cmp::Reverse(entry));
+                if newest.len() > n {
+                    newest.pop();
+                }
+            },
+        )
+        .await?;
+        Ok(newest
+            .into_sorted_vec()
+            .into_iter()
+            .map(|cmp::Reverse(ByModifiedTime(_, entry))| entry)
+            .collect())
+    }
+
+    // Walks the whole tree, running `f` on each regular file with at most
+    // `limit` calls in progress at once and giving their outputs to `g`.
+    //
+    // Stops at the first error, from the walk or from `f`, which is returned.
+    async fn map_files_concurrent<T, F, Fut, G>(
+        mut self,
+        limit: usize,
+        mut f: F,
+        mut g: G,
+    ) -> Result<()>
+    where
+        F: FnMut(Entry) -> Fut,
+        Fut: Future<Output = Result<T>>,
+        G: FnMut(T),
+    {
+        let limit = limit.max(1);
+        let mut running = Vec::new();
+        let mut exhausted = false;
+        future::poll_fn(|cx| loop {
+            while !exhausted && running.len() < limit {
+                match Pin::new(&mut self).poll_next(cx) {
+                    Poll::Ready(Some(Ok(entry))) => {
+                        if entry.file_type().is_ok_and(|ft| ft.is_file()) {
+                            running.push(Box::pin(f(entry)));
+                        }
+                    }
+                    Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                    Poll::Ready(None) => exhausted = true,
+                    Poll::Pending => break,
+                }
+            }
+            let count = running.len();
+            let mut i = 0;
+            while i < running.len() {
+                match running[i].as_mut().poll(cx) {
+                    Poll::Ready(output) => {
+                        drop(running.swap_remove(i));
+                        g(output?);
+                    }
+                    Poll::Pending => i += 1,
+                }
+            }
+            if exhausted && running.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+            if running.len() == count {
+                return Poll::Pending;
+            }
+        })
+        .await
+    }
+
+    /// Walks the whole tree and groups the paths of the regular files by
+    /// extension.
+    ///
+    /// Files without extension are grouped under an empty key. On Windows,
+    /// whose file systems are case insensitive, the extensions are lowercased
+    /// in ASCII.
+    ///
+    /// Stops at the first error, which is returned.
+    pub async fn collect_by_extension(mut self) -> Result<HashMap<OsString, Vec<PathBuf>>> {
+        let mut groups: HashMap<OsString, Vec<PathBuf>> = HashMap::new();
+        while let Some(entry) = self.next().await {
+            let entry = entry?;
+            if !entry.file_type().is_ok_and(|ft| ft.is_file()) {
+                continue;
+            }
+            let path = entry.path();
+            let ext = path.extension().unwrap_or_default();
+            #[cfg(windows)]
+            let ext = ext.to_ascii_lowercase();
+            groups.entry(ext.to_owned()).or_default().push(path);
+        }
+        Ok(groups)
+    }
+
+    /// Walks the whole tree and returns the path of every entry along with
+    /// all the errors encountered.
+    pub async fn collect_paths_lossy(mut self) -> (Vec<PathBuf>, Vec<WalkDirError>) {
+        let mut paths = Vec::new();
+        let mut errors = Vec::new();
+        while let Some(entry) = self.next().await {
+            match entry {
+                Ok(entry) => paths.push(entry.path()),
+                Err(e) => errors.push(e),
+            }
+        }
+        (paths, errors)
+    }
+
+    /// Walks the tree at `root` and returns the sorted paths of its entries,
+    /// to be compared later with [`diff`](Self::diff).
+    ///
+    /// Stops at the first error, which is returned.
+    pub async fn snapshot(root: impl AsRef<Path>) -> Result<Vec<PathBuf>> {
+        let mut paths = WalkDir::new(root).collect_paths().await?;
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Returns the paths added and removed between the `before` and `after`
+    /// snapshots of a tree, as returned by [`snapshot`](Self::snapshot).
+    ///
+    /// A removed path and an added path with the same file name are reported
+    /// as renamed instead, which is a guess. The paths are reported in the
+    /// order of the snapshots.
+    pub fn diff(before: &[PathBuf], after: &[PathBuf]) -> WalkDiff {
+        diff::diff(before, after)
+    }
+
+    /// Walks the trees at `root_a` and `root_b` side by side and returns a
+    /// stream of their differences.
+    ///
+    /// The trees are walked in name order, so that only the current entry of
+    /// each is held. The entries of a directory found in one tree only are
+    /// all reported. Errors are yielded and the walks go on.
+    pub fn difference(
+        root_a: impl AsRef<Path>,
+        root_b: impl AsRef<Path>,
+    ) -> impl Stream<Item = Result<DiffEntry>> + Send + Unpin {
+        let difference = diff::Difference::new(root_a.as_ref(), root_b.as_ref());
+        stream::unfold(difference, |mut difference| async move {
+            let item = difference.next().await?;
+            Some((item, difference))
+        })
+        .boxed()
+    }
+
+    /// Walks the tree at `root` and returns the total size in bytes of its
+    /// regular files along with all the errors encountered.
+    ///
+    /// When `allocated` is true, the space allocated on disk is summed
+    /// instead of the file lengths on Unix, from the number of 512 bytes
+    /// blocks of each file. Files with several hard links are counted once
+    /// per link.
+    pub async fn disk_usage(root: impl AsRef<Path>, allocated: bool) -> (u64, Vec<WalkDirError>) {
+        let mut wd = WalkDir::new(root);
+        let mut total = 0;
+        let mut errors = Vec::new();
+        while let Some(entry) = wd.next().await {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                }
+            };
+            if !entry.file_type().is_ok_and(|ft| ft.is_file()) {
+                continue;
+            }
+            match entry.async_metadata().await {
+                Ok(metadata) => total += file_size(&metadata, allocated),
+                Err(e) => errors.push(WalkDirError::new(entry.path(), e)),
+            }
+        }
+        (total, errors)
+    }
+
+    /// Walks the whole tree and renders it as the `tree` command does, for
+    /// debugging or display purposes.
+    ///
+    /// Directory names end with `/`. The configuration and filters apply but
+    /// the tree is walked depth first, with its entries sorted by name unless
+    /// another order was set, and the roots are not yielded as entries.
+    /// Entries are indented by their depth, even when their parents are
+    /// filtered out, e.g. by [`with_min_depth`](Self::with_min_depth).
+    ///
+    /// Stops at the first error, which is returned.
+    pub async fn directory_tree_string(mut self) -> Result<String> {
+        self.options.breadth_first = false;
+        self.options.ordered_by_depth = false;
+        self.options.contents_first = false;
+        self.options.parallelism = None;
+        if self.sort.is_none() {
+            self = self.with_sort_by_name();
+        }
+        let follow_links = self.options.follow_links;
+        let follow_root_links = self.options.follow_root_links;
+        // The roots as walked, with their rendered path and entries.
+        let mut roots = Vec::with_capacity(self.roots.len());
+        for root in &self.roots {
+            let walked = if follow_root_links {
+                let root = root.clone();
+                blocking::unblock(move || {
+                    if root.is_symlink() {
+                        fs::canonicalize(&root).unwrap_or(root)
+                    } else {
+                        root
+                    }
+                })
+                .await
+            } else {
+                root.clone()
+            };
+            roots.push((walked, root.clone(), Vec::new()));
+        }
+        let mut current = 0;
+        while let Some(entry) = self.next().await {
+            let entry = entry?;
+            let path = entry.path();
+            // Entries are walked root after root, each one `depth + 1` levels
+            // below its root.
+            let root = path.ancestors().nth(entry.depth() + 1);
+            while current < roots.len() && root != Some(&roots[current].0) {
+                current += 1;
+            }
+            let is_dir = match entry.file_type() {
+                Ok(ft) if ft.is_symlink() && follow_links => {
+                    let link = path.clone();
+                    blocking::unblock(move || fs::metadata(link))
+                        .await
+                        .is_ok_and(|m| m.is_dir())
+                }
+                Ok(ft) => ft.is_dir(),
+                Err(e) => return Err(WalkDirError::new(path, e)),
+            };
+            if let Some((_, _, entries)) = roots.get_mut(current) {
+                entries.push((path, entry.depth(), is_dir));
+            }
+        }
+        let mut tree = String::new();
+        for (_, root, entries) in roots {
+            render_tree(&mut tree, &root, &entries);
+        }
+        Ok(tree)
+    }
+}
+
+// An entry ordered by modification time only.
+struct ByModifiedTime(SystemTime, Entry);
+
+impl PartialEq for ByModifiedTime {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl Eq for ByModifiedTime {}
+
+impl PartialOrd for ByModifiedTime {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for ByModifiedTime {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+// Returns the length of a file, or the space allocated for it on Unix.
+fn file_size(metadata: &fs::Metadata, allocated: bool) -> u64 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+
+        if allocated {
+            return metadata.blocks() * 512;
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = allocated;
+    metadata.len()
+}
+
+// Renders a root and its entries, walked depth first, as the `tree` command.
+fn render_tree(tree: &mut String, root: &Path, entries: &[(PathBuf, usize, bool)]) {
+    // Whether each entry is the last of its siblings, walking backwards and
+    // keeping the directory of the entry last seen at each depth, as the
+    // parents may have been filtered out.
+    let mut lasts = vec![false; entries.len()];
+    let mut followed = Vec::new();
+    for (i, (path, depth, _)) in entries.iter().enumerate().rev() {
+        followed.resize(depth + 1, None);
+        lasts[i] = followed[*depth] != path.parent();
+        followed[*depth] = path.parent();
+    }
+    let root = root.display().to_string();
+    tree.push_str(&root);
+    if !root.ends_with(std::path::is_separator) {
+        tree.push('/');
+    }
+    tree.push('\n');
+    // Whether each ancestor of the current entry is the last of its siblings,
+    // the ones filtered out being left blank.
+    let mut ancestors = Vec::new();
+    for ((path, depth, is_dir), last) in entries.iter().zip(lasts) {
+        ancestors.resize(*depth, true);
+        for &ancestor in &ancestors {
+            tree.push_str(if ancestor { "    " } else { "│   " });
+        }
+        tree.push_str(if last { "└── " } else { "├── " });
+        tree.push_str(&path.file_name().unwrap_or_default().to_string_lossy());
+        if *is_dir {
+            tree.push('/');
+        }
+        tree.push('\n');
+        ancestors.push(last);
+    }
+}
+
+impl WalkDir {
+    // Polls the next item of the walk, roots included, see `RootedWalkDir`.
+    pub(crate) fn poll_item(&mut self, cx: &mut Context<'_>) -> Poll<Option<Result<RootedEntry>>> {
+        let this = self;
+        if this.terminated {
+            return Poll::Ready(None);
+        }
+        if let Some(token) = &this.cancellation {
+            if token.is_cancelled() {
+                // Drops the walk state, the stream being over.
+                this.entries = Some(Walking::Boxed(stream::empty().boxed()));
+                this.cancellation = None;
+            }
+        }
+        if this.entries.is_none() {
+            this.entries = Some(this.start());
+        }
+        if let Some(deadline) = &mut this.deadline {
+            let (duration, sleep) = (deadline.duration, &deadline.sleep);
+            let timer = deadline.timer.get_or_insert_with(|| sleep(duration));
+            if timer.as_mut().poll(cx).is_ready() {
+                let root = std::mem::take(&mut deadline.root);
+                this.deadline = None;
+                // Drops the walk state, the stream being over.
+                this.entries = Some(Walking::Boxed(stream::empty().boxed()));
+                let err = Error::new(io::ErrorKind::TimedOut, "the walk timed out");
+                return Poll::Ready(Some(Err(WalkDirError::new(root, err))));
+            }
+        }
+        let entries = this.entries.as_mut().expect("the walk was started");
+        let item = ready!(Pin::new(entries).poll_next(cx));
+        if item.is_none() {
+            // Drops the walk state, which must not be polled anymore.
+            this.entries = Some(Walking::Boxed(stream::empty().boxed()));
+            this.deadline = None;
+            this.terminated = true;
+        }
+        if let (Some(Err(_)), Some(stats)) = (&item, &this.stats) {
+            stats.add_error();
+        }
+        if let Some(Ok(RootedEntry::Entry(entry))) = &item {
+            for hook in &mut this.hooks {
+                hook(entry);
+            }
+        }
+        #[cfg(feature = "tokio")]
+        if let (Some(Ok(entry)), Some(progress)) = (&item, &this.progress) {
+            progress.send_modify(|progress| {
+                progress.entries_yielded += 1;
+                progress.current_path = entry.path();
+            });
+        }
+        Poll::Ready(item)
+    }
+}
+
+impl Stream for WalkDir {
+    type Item = Result<Entry>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            return Poll::Ready(match ready!(self.poll_item(cx)) {
+                // Only yielded through `with_root_entry`.
+                Some(Ok(RootedEntry::Root(_))) => continue,
+                Some(Ok(RootedEntry::Entry(entry))) => Some(Ok(entry)),
+                Some(Err(e)) => Some(Err(e)),
+                None => None,
+            });
+        }
+    }
+}
+
+/// Once it returned `None`, a `WalkDir` keeps returning `None`.
+impl futures_core::stream::FusedStream for WalkDir {
+    fn is_terminated(&self) -> bool {
+        self.terminated
+    }
+}
+
+/// Allows `for await` loops on nightly Rust, with the `async_iterator`
+/// feature.
+///
+/// The [`AsyncIterator`](std::async_iter::AsyncIterator) trait is unstable,
+/// this implementation will be available on stable Rust once it is
+/// stabilized.
+#[cfg(feature = "async_iterator")]
+impl std::async_iter::AsyncIterator for WalkDir {
+    type Item = Result<Entry>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Stream::poll_next(self, cx)
+    }
+}
+
+// The entries of a started walk.
+enum Walking {
+    // The walk of the roots, which can be branched, see `WalkDir::try_clone`.
+    Roots(WalkerStream),
+    // Combined with other streams, or over.
+    Boxed(BoxStream),
+}
+
+impl Stream for Walking {
+    type Item = Result<RootedEntry>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match &mut *self {
+            Walking::Roots(walk) => Pin::new(walk).poll_next(cx),
+            Walking::Boxed(entries) => entries.as_mut().poll_next(cx),
+        }
+    }
+}
+
+// Yields the items of a walker, which is kept between items so that the walk
+// can be branched.
+struct WalkerStream {
+    // `None` while an item is being read, or once the walk is over.
+    walker: Option<Box<Walker>>,
+    next: Option<NextItem>,
+}
+
+// Reads the next item of a walk, giving the walker back.
+type NextItem = BoxedFut<(Option<Result<RootedEntry>>, Box<Walker>)>;
+
+impl WalkerStream {
+    fn new(walker: Walker) -> Self {
+        Self {
+            walker: Some(Box::new(walker)),
+            next: None,
+        }
+    }
+}
+
+impl Stream for WalkerStream {
+    type Item = Result<RootedEntry>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(mut walker) = self.walker.take() {
+            let next = async move {
+                let item = walker.next().await;
+                (item, walker)
+            };
+            self.next = Some(next.boxed());
+        }
+        let next = match &mut self.next {
+            Some(next) => next,
+            None => return Poll::Ready(None),
+        };
+        let (item, walker) = ready!(next.as_mut().poll(cx));
+        self.next = None;
+        // The walker is dropped once the walk is over.
+        if item.is_some() {
+            self.walker = Some(walker);
+        }
+        Poll::Ready(item)
+    }
+}
+
+// Starts walking with the configuration of `wd`.
+fn walk_dir(wd: &mut WalkDir) -> WalkerStream {
+    let options = std::mem::take(&mut wd.options);
+    let mut restore = VecDeque::new();
+    let mut queue = VecDeque::new();
+    if let Some(resume) = wd.resume.take() {
+        restore.extend(resume.dirs);
+        queue.extend(resume.queue.into_iter().map(|dir| PendingDir {
+            path: dir.path,
+            depth: dir.depth,
+            ancestors: Vec::new(),
+            ignores: None,
+        }));
+    }
+    let ignore_files = ignores::file_names(&options);
+    let resolve = |path: &PathBuf| -> Vec<PathBuf> {
+        if path.is_absolute() {
+            vec![path.clone()]
+        } else {
+            wd.roots.iter().map(|root| root.join(path)).collect()
+        }
+    };
+    let excluded = options.excluded_paths.iter().flat_map(resolve).collect();
+    let included = options
+        .included_paths
+        .as_ref()
+        .map(|paths| paths.iter().flat_map(resolve).collect());
+    let walker = Walker {
+        roots: std::mem::take(&mut wd.roots).into_iter(),
+        root_read_dir: wd.root_read_dir.take(),
+        options,
+        ignore_files: ignore_files.into(),
+        excluded,
+        included,
+        filter: wd.filter.take(),
+        dir_enter_hooks: std::mem::take(&mut wd.dir_enter_hooks),
+        dir_exit_hooks: std::mem::take(&mut wd.dir_exit_hooks),
+        visited: HashSet::new(),
+        visited_ids: HashSet::new(),
+        seen_files: HashSet::new(),
+        root_device: None,
+        queue,
+        sort: wd.sort.take(),
+        stats: wd.stats.clone(),
+        rate: wd
+            .rate_limit
+            .clone()
+            .map(|(rate, sleep)| (RateLimiter::new(rate), sleep)),
+        open_dir: wd
+            .open_dir
+            .take()
+            .unwrap_or_else(|| Arc::new(|path: &Path| read_dir(path))),
+        checkpoint: wd.checkpoint.clone(),
+        restore,
+        dirs: Vec::new(),
+        reading: Vec::new(),
+        yielded: 0,
+        errors: 0,
+        done: false,
+        root_entry: wd.root_entry,
+    };
+    WalkerStream::new(walker)
+}
+
+struct Walker {
+    // Roots not walked yet.
+    roots: std::vec::IntoIter<PathBuf>,
+    // The first root, already open.
+    root_read_dir: Option<ReadDir>,
+    options: WalkDirOptions,
+    // Names of the ignore files to read in each directory.
+    ignore_files: Arc<[String]>,
+    // Paths to skip, resolved against the roots.
+    excluded: HashSet<PathBuf>,
+    // Subtrees to yield, resolved against the roots, when whitelisting.
+    included: Option<Vec<PathBuf>>,
+    filter: Option<FilterFn>,
+    dir_enter_hooks: Vec<DirHookFn>,
+    dir_exit_hooks: Vec<DirHookFn>,
+    // Canonical paths of the opened directories, when following links.
+    visited: HashSet<PathBuf>,
+    // Identifiers of every directory opened so far, when detecting cycles.
+    visited_ids: HashSet<(u64, u64)>,
+    // Identifiers of every file walked so far, when deduplicating.
+    seen_files: HashSet<(u64, u64)>,
+    // Device of the root, when staying on the same file system.
+    root_device: Option<u64>,
+    // Directories to traverse, in breadth first mode.
+    queue: VecDeque<PendingDir>,
+    // Orders the entries of each directory.
+    sort: Option<Sort>,
+    stats: Option<Arc<WalkDirStats>>,
+    // Limits the rate of the walk, sleeping with the given function.
+    rate: Option<(RateLimiter, SleepFn)>,
+    open_dir: OpenDirFn,
+    // Where to save the walk state on transient errors, when checkpointing.
+    checkpoint: Option<CheckpointSlot>,
+    // Directories being traversed by a resumed walk, to reopen.
+    restore: VecDeque<CheckpointDir>,
+    // Directories being traversed, the current one last.
+    dirs: Vec<Dir>,
+    // Directories being read, in parallel mode.
+    reading: Vec<ReadTask>,
+    // Number of entries yielded so far.
+    yielded: u64,
+    // Number of errors yielded so far.
+    errors: usize,
+    // Whether the walk was stopped.
+    done: bool,
+    // Whether the roots are yielded.
+    root_entry: bool,
+}
+
+impl Walker {
+    // Returns whether the directory at `path` is on the same device as the root,
+    // when staying on the same file system.
+    fn same_device(&self, path: &Path) -> io::Result<bool> {
+        match self.root_device {
+            Some(root_device) => Ok(sys::device_id(path)? == root_device),
+            None => Ok(true),
+        }
+    }
+
+    // Opens the directory at `path`, whose entries are at `depth`, inheriting
+    // the ignore rules of its parent.
+    async fn open_dir(
+        &mut self,
+        path: &Path,
+        depth: usize,
+        ignores: Option<Arc<Ignores>>,
+    ) -> io::Result<Dir> {
+        // Only the first directory opened may be already open.
+        let opened = self.root_read_dir.take();
+        let canonical = self.check_dir(path)?;
+        let rd = match opened {
+            Some(rd) => rd,
+            None => (self.open_dir)(path)?,
+        };
+        let ignores = if self.ignore_files.is_empty() {
+            None
+        } else {
+            Ignores::load(path, &self.ignore_files, ignores)?
+        };
+        let entries = match (&self.sort, self.options.buffer_size) {
+            (None, None) => Entries::Open(rd),
+            (None, Some(size)) => {
+                let size = size.max(1);
+                Entries::Batched {
+                    batch: Vec::new().into_iter(),
+                    next: Some(read_batch(rd, path.into(), size)),
+                    size,
+                }
+            }
+            (Some(_), _) => {
+                let entries = self.sort_entries(read_entries(rd, path)).await;
+                Entries::Buffered(entries.into_iter())
+            }
+        };
+        Ok(self.enter_dir(path.to_owned(), depth, entries, canonical, ignores))
+    }
+
+    // Checks that the directory at `path` can be entered, returning its
+    // canonical path when following links.
+    fn check_dir(&mut self, path: &Path) -> io::Result<Option<PathBuf>> {
+        let canonical = if self.options.follow_links {
+            let canonical = fs::canonicalize(path)?;
+            if self.visited.contains(&canonical) {
+                return Err(Error::other(format!(
+                    "file system loop found: points to ancestor {}",
+                    canonical.display()
+                )));
+            }
+            Some(canonical)
+        } else {
+            None
+        };
+        if self.options.detect_cycles && !self.visited_ids.insert(sys::file_id(path)?) {
+            return Err(Error::other("directory already visited"));
+        }
+        Ok(canonical)
+    }
+
+    // Registers a directory whose entries were read.
+    fn enter_dir(
+        &mut self,
+        path: PathBuf,
+        depth: usize,
+        entries: Entries,
+        canonical: Option<PathBuf>,
+        ignores: Option<Arc<Ignores>>,
+    ) -> Dir {
+        if let Some(canonical) = &canonical {
+            self.visited.insert(canonical.clone());
+        }
+        if let Some(stats) = &self.stats {
+            stats.add_dir();
+        }
+        #[cfg(feature = "tracing")]
+        let span = tracing::trace_span!("walk_dir", path = %path.display(), depth);
+        #[cfg(feature = "tracing")]
+        tracing::trace!(parent: &span, "directory entered");
+        for hook in &mut self.dir_enter_hooks {
+            hook(&path);
+        }
+        Dir {
+            path: path.into(),
+            depth,
+            entries,
+            canonical,
+            root: None,
+            entry: None,
+            ignores,
+            walked: HashSet::new(),
+            yielded: 0,
+            errors: 0,
+            #[cfg(feature = "tracing")]
+            span,
+        }
+    }
+
+    // Orders the entries of a directory, when sorting.
+    async fn sort_entries(&mut self, entries: Vec<Result<DirEntry>>) -> Vec<Result<DirEntry>> {
+        match &self.sort {
+            None => entries,
+            Some(Sort::By(cmp)) => sort_by(entries, cmp),
+            Some(Sort::ModifiedTime) => sort_by_modified_time(entries).await,
+            Some(Sort::Reversed) => {
+                let mut entries = entries;
+                entries.reverse();
+                entries
+            }
+        }
+    }
+
+    fn close_dir(&mut self, dir: Dir) {
+        if let Some(canonical) = dir.canonical {
+            self.visited.remove(&canonical);
+        }
+    }
+
+    // Returns a walker going on from the current state, saving checkpoints
+    // to `checkpoint`.
+    //
+    // The directories being traversed are read in memory, and read again for
+    // the clone on the thread pool.
+    fn try_clone(&mut self, checkpoint: Option<CheckpointSlot>) -> Walker {
+        let mut dirs = Vec::with_capacity(self.dirs.len());
+        for dir in &mut self.dirs {
+            let remaining = dir.remaining_entries();
+            let names = entry_names(&remaining);
+            dir.entries = Entries::Buffered(remaining.into_iter());
+            let (open_dir, path) = (self.open_dir.clone(), dir.path.clone());
+            let read = blocking::unblock(move || (read_again(&*open_dir, &path, names), None));
+            dirs.push(Dir {
+                path: dir.path.clone(),
+                depth: dir.depth,
+                entries: Entries::Batched {
+                    batch: Vec::new().into_iter(),
+                    next: Some(read),
+                    // Unused, the directory being read again as a whole.
+                    size: 0,
+                },
+                canonical: dir.canonical.clone(),
+                root: dir.root.clone(),
+                entry: dir.entry.clone(),
+                ignores: dir.ignores.clone(),
+                walked: dir.walked.clone(),
+                yielded: dir.yielded,
+                errors: dir.errors,
+                #[cfg(feature = "tracing")]
+                span: dir.span.clone(),
+            });
+        }
+        let reading = self
+            .reading
+            .iter()
+            .map(|read| self.read_task(read.pending.clone(), read.canonical.clone()))
+            .collect();
+        Walker {
+            roots: self.roots.clone(),
+            root_read_dir: None,
+            options: self.options.clone(),
+            ignore_files: self.ignore_files.clone(),
+            excluded: self.excluded.clone(),
+            included: self.included.clone(),
+            filter: self.filter.clone(),
+            dir_enter_hooks: Vec::new(),
+            dir_exit_hooks: Vec::new(),
+            visited: self.visited.clone(),
+            visited_ids: self.visited_ids.clone(),
+            seen_files: self.seen_files.clone(),
+            root_device: self.root_device,
+            queue: self.queue.clone(),
+            sort: self.sort.clone(),
+            stats: None,
+            rate: self.rate.clone(),
+            open_dir: self.open_dir.clone(),
+            checkpoint,
+            restore: self.restore.clone(),
+            dirs,
+            reading,
+            yielded: self.yielded,
+            errors: self.errors,
+            done: self.done,
+            root_entry: self.root_entry,
+        }
+    }
+}
+
+struct Dir {
+    path: Arc<Path>,
+    // The depth of the directory entries.
+    depth: usize,
+    entries: Entries,
+    canonical: Option<PathBuf>,
+    // The root or entry of this directory, yielded once exhausted in contents
+    // first mode. The entry is shared with the clones of the walk.
+    root: Option<RootEntry>,
+    entry: Option<Arc<Entry>>,
+    // The ignore rules applying to the directory entries.
+    ignores: Option<Arc<Ignores>>,
+    // Names of the entries walked, when checkpointing or resuming.
+    walked: HashSet<OsString>,
+    // The number of entries yielded, or to be yielded in contents first mode.
+    yielded: usize,
+    // The number of errors met walking the entries.
+    errors: usize,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+#[derive(Clone)]
+struct PendingDir {
+    path: PathBuf,
+    depth: usize,
+    // Canonical paths of the directory ancestors, when following links.
+    ancestors: Vec<PathBuf>,
+    ignores: Option<Arc<Ignores>>,
+}
+
+impl Dir {
+    // Reads the remaining entries in memory, waiting for the batch being read
+    // if any.
+    fn remaining_entries(&mut self) -> Vec<Result<DirEntry>> {
+        match &mut self.entries {
+            Entries::Open(rd) => read_entries(rd, &self.path),
+            Entries::Buffered(entries) => entries.collect(),
+            Entries::Batched { batch, next, .. } => {
+                let mut entries: Vec<_> = batch.collect();
+                if let Some(next) = next.take() {
+                    let (batch, rest) = future::block_on(next);
+                    entries.extend(batch);
+                    if let Some(rd) = rest {
+                        entries.extend(read_entries(rd, &self.path));
+                    }
+                }
+                entries
+            }
+        }
+    }
+
+    async fn next_entry(&mut self) -> Option<Result<DirEntry>> {
+        loop {
+            match &mut self.entries {
+                Entries::Open(rd) => {
+                    return rd
+                        .next()
+                        .map(|entry| entry.map_err(|e| WalkDirError::new(&*self.path, e)))
+                }
+                Entries::Buffered(entries) => return entries.next(),
+                Entries::Batched { batch, next, size } => {
+                    if let Some(entry) = batch.next() {
+                        return Some(entry);
+                    }
+                    let (entries, rest) = next.take()?.await;
+                    *batch = entries.into_iter();
+                    let path = self.path.clone();
+                    *next = rest.map(|rd| read_batch(rd, path, *size));
+                }
+            }
+        }
+    }
+}
+
+enum Entries {
+    Open(ReadDir),
+    Buffered(std::vec::IntoIter<Result<DirEntry>>),
+    // Read by batches of `size` entries on the thread pool.
+    Batched {
+        batch: std::vec::IntoIter<Result<DirEntry>>,
+        // The next batch being read, if the directory may have more entries.
+        next: Option<blocking::Task<Batch>>,
+        size: usize,
+    },
+}
+
+impl Entries {
+    // Returns whether the directory handle is still open.
+    fn is_open(&self) -> bool {
+        match self {
+            Entries::Open(_) => true,
+            Entries::Buffered(_) => false,
+            Entries::Batched { next, .. } => next.is_some(),
+        }
+    }
+}
+
+// Entries read from a directory, along with the handle to read the next ones
+// unless exhausted.
+type Batch = (Vec<Result<DirEntry>>, Option<ReadDir>);
+
+// Reads the next `size` entries of the directory at `path` on the thread pool.
+fn read_batch(mut rd: ReadDir, path: Arc<Path>, size: usize) -> blocking::Task<Batch> {
+    blocking::unblock(move || {
+        let entries = read_entries(rd.by_ref().take(size), &path);
+        let rest = (entries.len() == size).then_some(rd);
+        (entries, rest)
+    })
+}
+
+// Returns the names of `entries`, errors being duplicated, to read them again.
+fn entry_names(entries: &[Result<DirEntry>]) -> Vec<Result<OsString>> {
+    entries
+        .iter()
+        .map(|entry| match entry {
+            Ok(entry) => Ok(entry.file_name()),
+            Err(e) => {
+                let err = Error::new(e.io_error().kind(), e.io_error().to_string());
+                Err(WalkDirError::new(e.path(), err))
+            }
+        })
+        .collect()
+}
+
+// Reads again the entries of the directory at `path` named as `names`, in
+// the same order. Entries removed since are skipped.
+fn read_again(
+    open_dir: &(dyn Fn(&Path) -> io::Result<ReadDir> + Send + Sync),
+    path: &Path,
+    names: Vec<Result<OsString>>,
+) -> Vec<Result<DirEntry>> {
+    if names.is_empty() {
+        return Vec::new();
+    }
+    let mut fresh: HashMap<_, _> = match open_dir(path) {
+        Ok(rd) => rd
+            .flatten()
+            .map(|entry| (entry.file_name(), entry))
+            .collect(),
+        Err(e) => return vec![Err(WalkDirError::new(path, e))],
+    };
+    names
+        .into_iter()
+        .filter_map(|name| match name {
+            Ok(name) => fresh.remove(&name).map(Ok),
+            Err(e) => Some(Err(e)),
+        })
+        .collect()
+}
+
+// Reads the remaining entries of the directory at `path`.
+fn read_entries(
+    rd: impl Iterator<Item = io::Result<DirEntry>>,
+    path: &Path,
+) -> Vec<Result<DirEntry>> {
+    rd.map(|entry| entry.map_err(|e| WalkDirError::new(path, e)))
+        .collect()
+}
+
+#[derive(Clone)]
+enum Sort {
+    By(SortFn),
+    ModifiedTime,
+    Reversed,
+}
+
+// Compares `a` and `b` character by character, except for sequences of ASCII
+// digits which are compared by numeric value.
+fn natural_cmp(a: &str, b: &str) -> cmp::Ordering {
+    let (mut x, mut y) = (a, b);
+    loop {
+        let (c, d) = match (x.chars().next(), y.chars().next()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return cmp::Ordering::Less,
+            (Some(_), None) => return cmp::Ordering::Greater,
+            (Some(c), Some(d)) => (c, d),
+        };
+        if c.is_ascii_digit() && d.is_ascii_digit() {
+            let (m, rest_x) = split_number(x);
+            let (n, rest_y) = split_number(y);
+            let ordering = m.len().cmp(&n.len()).then_with(|| m.cmp(n));
+            if ordering != cmp::Ordering::Equal {
+                return ordering;
+            }
+            x = rest_x;
+            y = rest_y;
+        } else if c != d {
+            return c.cmp(&d);
+        } else {
+            x = &x[c.len_utf8()..];
+            y = &y[d.len_utf8()..];
+        }
+    }
+}
+
+// Splits the leading digits of `s`, without leading zeros, from the rest.
+fn split_number(s: &str) -> (&str, &str) {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, rest) = s.split_at(end);
+    (number.trim_start_matches('0'), rest)
+}
+
+// The maximum number of blocking tasks reading metadata for a single directory.
+const METADATA_CONCURRENCY: usize = 8;
+
+// Sorts the entries of a directory, errors coming last.
+fn sort_by(mut entries: Vec<Result<DirEntry>>, cmp: &SortFn) -> Vec<Result<DirEntry>> {
+    entries.sort_by(|a, b| match (a, b) {
+        (Ok(a), Ok(b)) => cmp(a, b),
+        (Ok(_), Err(_)) => cmp::Ordering::Less,
+        (Err(_), Ok(_)) => cmp::Ordering::Greater,
+        (Err(_), Err(_)) => cmp::Ordering::Equal,
+    });
+    entries
+}
+
+// Sorts the entries of a directory newest first.
+//
+// Entries whose modification time cannot be read are replaced by an error,
+// errors coming last.
+async fn sort_by_modified_time(entries: Vec<Result<DirEntry>>) -> Vec<Result<DirEntry>> {
+    let (entries, mut errors): (Vec<_>, Vec<_>) = entries.into_iter().partition(Result::is_ok);
+    let count = entries.len();
+    let mut entries = entries.into_iter().flatten();
+    let chunk_size = count.div_ceil(METADATA_CONCURRENCY).max(1);
+    let tasks: Vec<_> = (0..METADATA_CONCURRENCY)
+        .map(|_| entries.by_ref().take(chunk_size).collect::<Vec<_>>())
+        .filter(|chunk| !chunk.is_empty())
+        .map(|chunk| {
+            blocking::unblock(move || {
+                chunk
+                    .into_iter()
+                    .map(|entry| (entry.metadata().and_then(|m| m.modified()), entry))
+                    .collect::<Vec<_>>()
+            })
+        })
+        .collect();
+    let mut timed = Vec::new();
+    for task in tasks {
+        for (modified, entry) in task.await {
+            match modified {
+                Ok(modified) => timed.push((modified, entry)),
+                Err(e) => errors.push(Err(WalkDirError::new(entry.path(), e))),
+            }
+        }
+    }
+    timed.sort_by_key(|&(modified, _)| cmp::Reverse(modified));
+    timed
+        .into_iter()
+        .map(|(_, entry)| Ok(entry))
+        .chain(errors)
+        .collect()
+}
+
+// A directory being read on the thread pool, in parallel mode.
+struct ReadTask {
+    // The directory as queued, to read it again when branching the walk.
+    pending: PendingDir,
+    canonical: Option<PathBuf>,
+    task: blocking::Task<ReadOutput>,
+}
+
+// A directory read as a whole on the thread pool, in parallel mode.
+struct ReadOutput {
+    pending: PendingDir,
+    canonical: Option<PathBuf>,
+    entries: io::Result<Vec<Result<DirEntry>>>,
+    ignores: Option<Arc<Ignores>>,
+}
+
+fn read_whole_dir(
+    mut pending: PendingDir,
+    canonical: Option<PathBuf>,
+    ignore_files: &[String],
+    open_dir: &(dyn Fn(&Path) -> io::Result<ReadDir> + Send + Sync),
+) -> ReadOutput {
+    let mut ignores = pending.ignores.take();
+    let entries = open_dir(&pending.path).and_then(|rd| {
+        if !ignore_files.is_empty() {
+            ignores = Ignores::load(&pending.path, ignore_files, ignores.take())?;
+        }
+        Ok(read_entries(rd, &pending.path))
+    });
+    ReadOutput {
+        pending,
+        canonical,
+        entries,
+        ignores,
+    }
+}
+
+// Reads in memory the oldest open directories until less than `max_open` remain open.
+async fn close_oldest(dirs: &mut [Dir], max_open: usize) {
+    let mut open = dirs.iter().filter(|dir| dir.entries.is_open()).count();
+    for dir in dirs.iter_mut() {
+        if open < max_open {
+            break;
+        }
+        match &mut dir.entries {
+            Entries::Open(rd) => {
+                let entries = read_entries(rd, &dir.path);
+                dir.entries = Entries::Buffered(entries.into_iter());
+                open -= 1;
+            }
+            Entries::Batched { batch, next, .. } => {
+                if let Some(next) = next.take() {
+                    let mut entries: Vec<_> = batch.collect();
+                    let (batch, rest) = next.await;
+                    entries.extend(batch);
+                    if let Some(rd) = rest {
+                        entries.extend(read_entries(rd, &dir.path));
+                    }
+                    dir.entries = Entries::Buffered(entries.into_iter());
+                    open -= 1;
+                }
+            }
+            Entries::Buffered(_) => {}
+        }
+    }
+}
+
+impl Walker {
+    // Returns the next item of the walk, or `None` once it is over.
+    async fn next(&mut self) -> Option<Result<RootedEntry>> {
+        if let Some(max) = self.options.max_entries {
+            if self.yielded >= max {
+                self.stop();
+            }
+        }
+        while !self.done {
+            if let Some(dir) = self.restore.pop_front() {
+                if let Some(item) = self.restore_dir(dir).await {
+                    return Some(rooted(item));
+                }
+                continue;
+            }
+            // The index of the current directory, if any.
+            let current = self.dirs.len().wrapping_sub(1);
+            let item = match self.dirs.last_mut() {
+                Some(dir)
+                    if self
+                        .options
+                        .entry_limit_per_dir
+                        .is_some_and(|n| dir.yielded >= n) =>
+                {
+                    self.pop_dir().await
+                }
+                Some(dir) => {
+                    let item = match dir.next_entry().await {
+                        Some(Ok(entry)) if dir.walked.is_empty() && self.checkpoint.is_none() => {
+                            let depth = dir.depth;
+                            self.walk_entry(entry, depth).await.map(rooted)
+                        }
+                        Some(Ok(entry)) => {
+                            let depth = dir.depth;
+                            self.walk_tracked_entry(entry, depth).await.map(rooted)
+                        }
+                        Some(Err(e)) => {
+                            self.save_checkpoint(&e, Retry::Current);
+                            Some(Err(e))
+                        }
+                        None => self.pop_dir().await,
+                    };
+                    self.limit_dir_errors(current, item)
+                }
+                None if self.options.parallelism.is_some()
+                    && !(self.queue.is_empty() && self.reading.is_empty()) =>
+                {
+                    self.read_next_dir().await.map(rooted)
+                }
+                None => match self.queue.pop_front() {
+                    Some(pending) => self.open_pending(pending).await.map(rooted),
+                    None => {
+                        let root = self.roots.next()?;
+                        self.open_root(root).await
+                    }
+                },
+            };
+            if let Some(item) = item {
+                match &item {
+                    Ok(_entry) => {
+                        trace!(path = %_entry.path().display(), depth = _entry.depth(), "entry yielded");
+                        self.yielded += 1;
+                    }
+                    Err(_e) => {
+                        trace!(error = %_e, "error encountered");
+                        self.errors += 1;
+                        if self.errors == self.options.error_limit {
+                            self.stop();
+                        }
+                    }
+                }
+                return Some(item);
+            }
+        }
+        None
+    }
+
+    // Counts an error met walking the entries of the directory at `index`,
+    // skipping its remaining entries once the limit is reached.
+    fn limit_dir_errors<T>(&mut self, index: usize, item: Option<Result<T>>) -> Option<Result<T>> {
+        let (max, e) = match (self.options.max_io_errors_per_dir, item) {
+            (Some(max), Some(Err(e))) => (max.max(1), e),
+            (_, item) => return item,
+        };
+        let dir = match self.dirs.get_mut(index) {
+            Some(dir) => dir,
+            None => return Some(Err(e)),
+        };
+        dir.errors += 1;
+        if dir.errors < max {
+            return Some(Err(e));
+        }
+        // The directory is then closed as if exhausted.
+        dir.entries = Entries::Buffered(Vec::new().into_iter());
+        let err = Error::new(
+            e.io_error().kind(),
+            format!(
+                "{} errors walking the directory, its remaining entries are skipped, last error: {}",
+                max, e
+            ),
+        );
+        Some(Err(WalkDirError::new(&*dir.path, err)))
+    }
+
+    // Returns whether the size of a regular file is within the configured
+    // bounds, reading its metadata if needed.
+    fn size_in_bounds(&self, entry: &Entry) -> bool {
+        let (min, max) = match (self.options.min_file_size, self.options.max_file_size) {
+            (None, None) => return true,
+            (min, max) => (min.unwrap_or(0), max.unwrap_or(u64::MAX)),
+        };
+        entry
+            .metadata()
+            .is_ok_and(|m| (min..=max).contains(&m.len()))
+    }
+
+    // Stops the walk, dropping the directories not traversed yet.
+    fn stop(&mut self) {
+        self.done = true;
+        self.dirs.clear();
+        self.queue.clear();
+        self.reading.clear();
+    }
+
+    // Saves the walk state and stops the walk if `e` is transient, when
+    // checkpointing. `retry` is the directory that could not be read.
+    fn save_checkpoint(&mut self, e: &io::Error, retry: Retry) {
+        let slot = match &self.checkpoint {
+            Some(slot) if is_transient(e) => slot.clone(),
+            _ => return,
+        };
+        let dirs = self.dirs.iter().map(|dir| CheckpointDir {
+            path: dir.path.to_path_buf(),
+            depth: dir.depth,
+            walked: dir.walked.clone(),
+        });
+        let reading = self.reading.iter().map(|read| CheckpointDir {
+            path: read.pending.path.clone(),
+            depth: read.pending.depth,
+            walked: HashSet::new(),
+        });
+        let queue = self.queue.iter().map(|pending| CheckpointDir {
+            path: pending.path.clone(),
+            depth: pending.depth,
+            walked: HashSet::new(),
+        });
+        let mut checkpoint = WalkCheckpoint {
+            dirs: dirs.collect(),
+            queue: reading.chain(queue).collect(),
+            roots: self.roots.as_slice().to_vec(),
+        };
+        match retry {
+            Retry::Current => {}
+            Retry::Root(root) => checkpoint.roots.insert(0, root),
+            Retry::Queued(dir) => checkpoint.queue.insert(0, dir),
+            Retry::Restored(dir) => checkpoint.dirs.push(dir),
+        }
+        checkpoint.dirs.extend(self.restore.drain(..));
+        *slot.lock().unwrap_or_else(PoisonError::into_inner) = Some(checkpoint);
+        self.stop();
+    }
+
+    // Reopens a directory being traversed when a resumed walk was interrupted.
+    async fn restore_dir(&mut self, restored: CheckpointDir) -> Option<Result<Entry>> {
+        if let Some(max_open) = self.options.max_open {
+            close_oldest(&mut self.dirs, max_open.max(1)).await;
+        }
+        match self.open_dir(&restored.path, restored.depth, None).await {
+            Err(e) => {
+                let path = restored.path.clone();
+                self.save_checkpoint(&e, Retry::Restored(restored));
+                Some(Err(WalkDirError::new(path, e)))
+            }
+            Ok(mut dir) => {
+                dir.walked = restored.walked;
+                self.dirs.push(dir);
+                None
+            }
+        }
+    }
+
+    // Processes an entry of the current directory unless walked before the
+    // walk was resumed, recording it when checkpointing.
+    async fn walk_tracked_entry(&mut self, entry: DirEntry, depth: usize) -> Option<Result<Entry>> {
+        let name = entry.file_name();
+        let index = self.dirs.len() - 1;
+        if self.dirs[index].walked.contains(&name) {
+            return None;
+        }
+        let item = self.walk_entry(entry, depth).await;
+        if self.checkpoint.is_some() {
+            // The directory is gone if the walk was stopped.
+            if let Some(dir) = self.dirs.get_mut(index) {
+                dir.walked.insert(name);
+            }
+        }
+        item
+    }
+
+    // Opens `root`, returning its own entry when requested.
+    async fn open_root(&mut self, mut root: PathBuf) -> Option<Result<RootedEntry>> {
+        if self.excluded.contains(&root) {
+            return None;
+        }
+        if self.options.follow_root_links && root.is_symlink() {
+            match fs::canonicalize(&root) {
+                Err(e) => {
+                    self.save_checkpoint(&e, Retry::Root(root.clone()));
+                    return Some(Err(WalkDirError::new(root, e)));
+                }
+                Ok(canonical) => root = canonical,
+            }
+        }
+        if self.options.same_file_system {
+            match sys::device_id(&root) {
+                Err(e) => {
+                    self.save_checkpoint(&e, Retry::Root(root.clone()));
+                    return Some(Err(WalkDirError::new(root, e)));
+                }
+                Ok(device) => self.root_device = Some(device),
+            }
+        }
+        let mut dir = match self.open_dir(&root, 0, None).await {
+            Err(e) => {
+                self.save_checkpoint(&e, Retry::Root(root.clone()));
+                return Some(Err(WalkDirError::new(root, e)));
+            }
+            Ok(dir) => dir,
+        };
+        let mut item = None;
+        if self.root_entry {
+            match RootEntry::read(root.clone()) {
+                Err(e) => item = Some(Err(WalkDirError::new(root, e))),
+                Ok(entry) => {
+                    if self.options.contents_first {
+                        dir.root = Some(entry);
+                    } else {
+                        item = Some(Ok(RootedEntry::Root(entry)));
+                    }
+                }
+            }
+        }
+        self.dirs.push(dir);
+        item
+    }
+
+    // Opens a directory queued in breadth first mode.
+    async fn open_pending(&mut self, pending: PendingDir) -> Option<Result<Entry>> {
+        self.visited = pending.ancestors.into_iter().collect();
+        let opened = self
+            .open_dir(&pending.path, pending.depth, pending.ignores)
+            .await;
+        match opened {
+            Err(e) => {
+                self.save_checkpoint(&e, Retry::queued(&pending.path, pending.depth));
+                Some(Err(WalkDirError::new(pending.path, e)))
+            }
+            Ok(dir) => {
+                self.dirs.push(dir);
+                None
+            }
+        }
+    }
+
+    // Reads the directory `pending` on the thread pool, in parallel mode.
+    fn read_task(&self, pending: PendingDir, canonical: Option<PathBuf>) -> ReadTask {
+        let ignore_files = self.ignore_files.clone();
+        let open_dir = self.open_dir.clone();
+        let task = {
+            let (pending, canonical) = (pending.clone(), canonical.clone());
+            blocking::unblock(move || read_whole_dir(pending, canonical, &ignore_files, &*open_dir))
+        };
+        ReadTask {
+            pending,
+            canonical,
+            task,
+        }
+    }
+
+    // Reads the queued directories concurrently and enters the first one read,
+    // in parallel mode.
+    async fn read_next_dir(&mut self) -> Option<Result<Entry>> {
+        let parallelism = self.options.parallelism.unwrap_or(1).max(1);
+        while self.reading.len() < parallelism {
+            let pending = match self.queue.pop_front() {
+                Some(pending) => pending,
+                None => break,
+            };
+            // Deeper directories wait for the current level to be read.
+            if self.options.ordered_by_depth
+                && self
+                    .reading
+                    .iter()
+                    .any(|read| read.pending.depth < pending.depth)
+            {
+                self.queue.push_front(pending);
+                break;
+            }
+            self.visited = pending.ancestors.iter().cloned().collect();
+            let canonical = match self.check_dir(&pending.path) {
+                Err(e) => {
+                    self.save_checkpoint(&e, Retry::queued(&pending.path, pending.depth));
+                    return Some(Err(WalkDirError::new(pending.path, e)));
+                }
+                Ok(canonical) => canonical,
+            };
+            let read = self.read_task(pending, canonical);
+            self.reading.push(read);
+        }
+        let reading = &mut self.reading;
+        let read = future::poll_fn(|cx| {
+            let ready =
+                reading
+                    .iter_mut()
+                    .enumerate()
+                    .find_map(|(i, read)| match read.task.poll(cx) {
+                        Poll::Ready(read) => Some((i, read)),
+                        Poll::Pending => None,
+                    });
+            match ready {
+                Some((i, read)) => {
+                    drop(reading.swap_remove(i));
+                    Poll::Ready(read)
+                }
+                None => Poll::Pending,
+            }
+        })
+        .await;
+        let ReadOutput {
+            pending,
+            canonical,
+            entries,
+            ignores,
+        } = read;
+        match entries {
+            Err(e) => {
+                self.save_checkpoint(&e, Retry::queued(&pending.path, pending.depth));
+                Some(Err(WalkDirError::new(pending.path, e)))
+            }
+            Ok(entries) => {
+                let entries = self.sort_entries(entries).await;
+                self.visited = pending.ancestors.into_iter().collect();
+                let dir = self.enter_dir(
+                    pending.path,
+                    pending.depth,
+                    Entries::Buffered(entries.into_iter()),
+                    canonical,
+                    ignores,
+                );
+                self.dirs.push(dir);
+                None
+            }
+        }
+    }
+
+    // Closes the exhausted current directory, returning its own entry in
+    // contents first mode.
+    async fn pop_dir(&mut self) -> Option<Result<RootedEntry>> {
+        let mut dir = self.dirs.pop()?;
+        let (root, entry) = (dir.root.take(), dir.entry.take());
+        for hook in &mut self.dir_exit_hooks {
+            hook(&dir.path);
+        }
+        self.close_dir(dir);
+        match (root, entry) {
+            (Some(root), _) => Some(Ok(RootedEntry::Root(root))),
+            (None, Some(entry)) => Some(rooted(unshare(entry, &self.open_dir).await)),
+            (None, None) => None,
+        }
+    }
+
+    // Processes an entry of the current directory, returning the item to
+    // yield if any.
+    async fn walk_entry(&mut self, entry: DirEntry, depth: usize) -> Option<Result<Entry>> {
+        if let Some((rate, sleep)) = &mut self.rate {
+            if let Some(delay) = rate.acquire(std::time::Instant::now()) {
+                sleep(delay).await;
+            }
+        }
+        let ft = match entry.file_type() {
+            Err(e) => return Some(Err(WalkDirError::new(entry.path(), e))),
+            Ok(ft) => ft,
+        };
+        // The index of the directory the entry is read from.
+        let parent = self.dirs.len().checked_sub(1);
+        let parent = parent.expect("entries are read from a directory");
+        let mut entry = Entry::new(entry, depth, self.dirs[parent].path.clone(), ft);
+        if self.options.prefetch_metadata {
+            let _ = entry.metadata();
+        }
+        if let Some(stats) = &self.stats {
+            let bytes = if ft.is_file() {
+                entry.metadata().map_or(0, |m| m.len())
+            } else {
+                0
+            };
+            stats.add_entry(bytes);
+        }
+        #[cfg(feature = "tracing")]
+        let span = self.dirs.last().and_then(|dir| dir.span.id());
+        if !self.excluded.is_empty() && self.excluded.contains(&entry.path()) {
+            trace!(parent: span, path = %entry.path().display(), "excluded entry skipped");
+            return None;
+        }
+        // Whether the entry only leads to an included subtree.
+        let mut leading = false;
+        if let Some(included) = &self.included {
+            let path = entry.path();
+            if !included.iter().any(|subtree| path.starts_with(subtree)) {
+                if ft.is_file() || !included.iter().any(|subtree| subtree.starts_with(&path)) {
+                    trace!(parent: span, path = %path.display(), "entry not included");
+                    return None;
+                }
+                leading = true;
+            }
+        }
+        let ignores = self.dirs.last().and_then(|dir| dir.ignores.clone());
+        if let Some(ignores) = &ignores {
+            if ignores.is_ignored(&entry.path(), ft.is_dir()) {
+                trace!(parent: span, path = %entry.path().display(), "entry ignored by an ignore file");
+                return None;
+            }
+        }
+        if self.options.deduplicate
+            && !ft.is_dir()
+            && (self.options.follow_links || !ft.is_symlink())
+        {
+            if let Ok(id) = sys::file_id(&entry.path()) {
+                if !self.seen_files.insert(id) {
+                    trace!(parent: span, path = %entry.path().display(), "duplicate entry ignored");
+                    return None;
+                }
+            }
+        }
+        if ft.is_file() && !self.size_in_bounds(&entry) {
+            trace!(parent: span, path = %entry.path().display(), "file size out of bounds");
+            return None;
+        }
+        let mut filtering = match &self.filter {
+            Some(filter) => {
+                let shared = Arc::new(entry);
+                let filtering = call_filter(filter, shared.clone()).await;
+                entry = match unshare(shared, &self.open_dir).await {
+                    Ok(entry) => entry,
+                    Err(e) => return Some(Err(e)),
+                };
+                match filtering {
+                    Ok(filtering) => filtering,
+                    Err(e) => {
+                        if self.options.filter_error_policy == FilterErrorPolicy::Stop {
+                            self.stop();
+                        }
+                        return Some(Err(WalkDirError::new(entry.path(), e)));
+                    }
+                }
+            }
+            None => Filtering::Continue,
+        };
+        if filtering != Filtering::Continue {
+            trace!(parent: span, path = %entry.path().display(), ?filtering, "entry filtered");
+        } else if leading {
+            filtering = Filtering::Ignore;
+        }
+        match filtering {
+            Filtering::Stop if depth >= self.options.min_depth => {
+                self.stop();
+                return Some(Ok(entry));
+            }
+            Filtering::Stop | Filtering::IgnoreAndStop => {
+                self.stop();
+                return None;
+            }
+            _ => {}
+        }
+        let descend = match self.options.max_depth {
+            Some(max) => depth < max,
+            None => true,
+        };
+        let is_dir = if ft.is_symlink() && self.options.follow_links {
+            let limit = self.options.symlink_limit.unwrap_or(DEFAULT_SYMLINK_LIMIT);
+            if let Err(e) = check_symlink_chain(&entry.path(), limit) {
+                return Some(Err(WalkDirError::new(entry.path(), e)));
+            }
+            // A broken link is returned as is.
+            fs::metadata(entry.path()).is_ok_and(|m| m.is_dir())
+        } else {
+            ft.is_dir()
+        };
+        let mut opened = false;
+        if is_dir && descend && filtering != Filtering::IgnoreDir {
+            let path = entry.path();
+            match self.same_device(&path) {
+                Err(e) => {
+                    self.save_checkpoint(&e, Retry::Current);
+                    return Some(Err(WalkDirError::new(path, e)));
+                }
+                Ok(false) => filtering = Filtering::IgnoreDir,
+                Ok(true)
+                    if self.options.breadth_first
+                        || self.options.ordered_by_depth
+                        || self.options.parallelism.is_some() =>
+                {
+                    let ancestors = self.visited.iter().cloned().collect();
+                    self.queue.push_back(PendingDir {
+                        path,
+                        depth: depth + 1,
+                        ancestors,
+                        ignores,
+                    });
+                }
+                Ok(true) => {
+                    if let Some(max_open) = self.options.max_open {
+                        close_oldest(&mut self.dirs, max_open.max(1)).await;
+                    }
+                    match self.open_dir(&path, depth + 1, ignores).await {
+                        Err(e) => {
+                            self.save_checkpoint(&e, Retry::Current);
+                            return Some(Err(WalkDirError::new(path, e)));
+                        }
+                        Ok(dir) => {
+                            self.dirs.push(dir);
+                            opened = true;
+                        }
+                    }
+                }
+            }
+        }
+        match filtering {
+            Filtering::Continue if depth >= self.options.min_depth => {
+                if let Some(dir) = self.dirs.get_mut(parent) {
+                    dir.yielded += 1;
+                }
+                match self.dirs.last_mut() {
+                    Some(dir) if opened && self.options.contents_first => {
+                        dir.entry = Some(Arc::new(entry));
+                        None
+                    }
+                    _ => Some(Ok(entry)),
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+// The directory to read again when resuming a walk interrupted by an error.
+enum Retry {
+    // The current directory, which is saved anyway.
+    Current,
+    Root(PathBuf),
+    Queued(CheckpointDir),
+    Restored(CheckpointDir),
+}
+
+impl Retry {
+    fn queued(path: &Path, depth: usize) -> Self {
+        Retry::Queued(CheckpointDir {
+            path: path.to_owned(),
+            depth,
+            walked: HashSet::new(),
+        })
+    }
+}
+
+// Returns whether `e` may not occur when trying again, such as a network
+// failure, as opposed to errors tied to the file system content.
+fn is_transient(e: &io::Error) -> bool {
+    !matches!(
+        e.kind(),
+        io::ErrorKind::NotFound
+            | io::ErrorKind::PermissionDenied
+            | io::ErrorKind::NotADirectory
+            | io::ErrorKind::InvalidInput
+            | io::ErrorKind::InvalidData
+            | io::ErrorKind::Other
+    )
+}
+
+// Boxes the sleep function given to `with_rate_limit` or `with_timeout`.
+fn sleep_fn<F, Fut>(sleep: F) -> SleepFn
+where
+    F: Fn(Duration) -> Fut + Send + Sync + 'static,
+    Fut: Future + Send + 'static,
+{
+    Arc::new(move |duration| {
+        let sleep = sleep(duration);
+        async move {
+            sleep.await;
+        }
+        .boxed()
+    })
+}
+
+// Calls `filter` on `entry`, the lock being released before the returned
+// future is polled.
+fn call_filter(filter: &FilterFn, entry: Arc<Entry>) -> BoxedFut<io::Result<Filtering>> {
+    filter.lock().unwrap_or_else(PoisonError::into_inner)(entry)
+}
+
+// Takes back an entry once shared with a filter, reading it again on the
+// thread pool if the filter retained it.
+async fn unshare(entry: Arc<Entry>, open_dir: &OpenDirFn) -> Result<Entry> {
+    let shared = match Arc::try_unwrap(entry) {
+        Ok(entry) => return Ok(entry),
+        Err(shared) => shared,
+    };
+    let open_dir = open_dir.clone();
+    blocking::unblock(move || {
+        shared
+            .read_again(&*open_dir)
+            .map_err(|e| WalkDirError::new(shared.path(), e))
+    })
+    .await
+}
+
+// The default length limit of symbolic link chains.
+const DEFAULT_SYMLINK_LIMIT: usize = 40;
+
+// Fails if the chain of symbolic links starting at `link` is longer than
+// `limit`. Other errors are left to the system when following the chain.
+fn check_symlink_chain(link: &Path, limit: usize) -> io::Result<()> {
+    let mut link = link.to_owned();
+    for _ in 0..limit {
+        let target = match fs::read_link(&link) {
+            Ok(target) => target,
+            Err(_) => return Ok(()),
+        };
+        link = match link.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+        if !link.is_symlink() {
+            return Ok(());
+        }
+    }
+    Err(Error::other(format!(
+        "too many levels of symbolic links, the limit is {}",
+        limit
+    )))
+}
+
+// Wraps an item of a directory walk.
+fn rooted(item: Result<Entry>) -> Result<RootedEntry> {
+    item.map(RootedEntry::Entry)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::OsStr;
+    use std::io::{ErrorKind, Result};
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime};
+
+    use futures_lite::future::{self, block_on};
+    use futures_lite::stream::StreamExt;
+
+    use super::checkpoint::CheckpointDir;
+    use super::ext::AsyncDirEntryExt;
+    use super::rate::RateLimiter;
+    use super::{
+        DiffEntry, Entry, FilterErrorPolicy, Filtering, OpenDirFn, RootedEntry, VirtualFileType,
+        VirtualTree, VirtualWalkDir, WalkCheckpoint, WalkDir, WalkDirBuilder, WalkDirOptions,
+    };
+
+    // Fails to compile if a public type loses its thread safety.
+    #[test]
+    fn thread_safety() {
+        fn assert_send<T: Send>() {}
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send::<WalkDir>();
+        assert_send::<super::PeekableWalkDir>();
+        assert_send::<super::RootedWalkDir>();
+        assert_send_sync::<RootedEntry>();
+        assert_send_sync::<Entry>();
+        assert_send_sync::<super::TransformedEntry>();
+        assert_send_sync::<super::WalkDirError>();
+        assert_send_sync::<WalkDirOptions>();
+        assert_send_sync::<WalkCheckpoint>();
+        assert_send_sync::<super::WalkDiff>();
+        assert_send_sync::<DiffEntry>();
+        assert_send_sync::<super::WalkDirStats>();
+        assert_send_sync::<VirtualWalkDir>();
+    }
+
+    #[test]
+    fn walk_dir_empty() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let mut wd = WalkDir::new(root.path());
+            assert!(wd.next().await.is_none());
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn fused() -> Result<()> {
+        use futures_core::stream::FusedStream;
+
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::write(root.path().join("f.txt"), b"").await?;
+
+            let mut wd = WalkDir::new(root.path());
+            assert!(!wd.is_terminated());
+            wd.next().await.unwrap()?;
+            assert!(wd.next().await.is_none());
+            assert!(wd.is_terminated());
+            assert!(wd.next().await.is_none());
+            assert!(wd.next().await.is_none());
+
+            let mut wd = WalkDir::new(root.path()).interleave(WalkDir::new(root.path()));
+            while wd.next().await.is_some() {}
+            assert!(wd.next().await.is_none());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn walk_dir_not_exist() {
+        block_on(async {
+            let mut wd = WalkDir::new("foobar");
+            match wd.next().await.unwrap() {
+                Ok(_) => panic!("want error"),
+                Err(e) => assert_eq!(e.kind(), ErrorKind::NotFound),
+            }
+        })
+    }
+
+    #[test]
+    fn walk_dir_files() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let d2 = d1.join("d2");
+            let f3 = d2.join("f3.txt");
+
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let want = vec![
+                d1.to_owned(),
+                d2.to_owned(),
+                f3.to_owned(),
+                f2.to_owned(),
+                f1.to_owned(),
+            ];
+            let mut wd = WalkDir::new(root.path());
+
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                let entry = entry.unwrap();
+                got.push(entry.path());
+            }
+            got.sort();
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn filter_dirs() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let d2 = d1.join("d2");
+            let f3 = d2.join("f3.txt");
+
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let want = vec![f3.to_owned(), f2.to_owned(), f1.to_owned()];
+
+            let mut wd = WalkDir::new(root.path()).filter(|entry| async move {
+                match entry.file_type() {
+                    Ok(ft) if ft.is_dir() => Filtering::Ignore,
+                    _ => Filtering::Continue,
+                }
+            });
+
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                let entry = entry.unwrap();
+                got.push(entry.path());
+            }
+            got.sort();
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn filter_dirs_no_traverse() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let d2 = d1.join("d2");
+            let f3 = d2.join("f3.txt");
+
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let want = vec![d1, f2.to_owned(), f1.to_owned()];
+
+            let mut wd = WalkDir::new(root.path()).filter(move |entry| {
+                let d2 = d2.clone();
+                async move {
+                    if entry.path() == d2 {
+                        Filtering::IgnoreDir
+                    } else {
+                        Filtering::Continue
+                    }
+                }
+            });
+
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                let entry = entry.unwrap();
+                got.push(entry.path());
+            }
+            got.sort();
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn max_depth() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let d2 = d1.join("d2");
+            let f3 = d2.join("f3.txt");
+
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let mut wd = WalkDir::new(root.path()).with_max_depth(0);
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                got.push(entry.unwrap().path());
+            }
+            got.sort();
+            assert_eq!(got, vec![d1.to_owned(), f1.to_owned()]);
+
+            let mut wd = WalkDir::new(root.path())
+                .filter(|entry| async move {
+                    match entry.file_type() {
+                        Ok(ft) if ft.is_dir() => Filtering::Ignore,
+                        _ => Filtering::Continue,
+                    }
+                })
+                .with_max_depth(1);
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                got.push(entry.unwrap().path());
+            }
+            got.sort();
+            assert_eq!(got, vec![f2, f1]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn min_depth() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let d2 = d1.join("d2");
+            let f3 = d2.join("f3.txt");
+
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let want = vec![d2.to_owned(), f3.to_owned(), f2.to_owned()];
+
+            let mut wd = WalkDir::new(root.path()).with_min_depth(1);
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                got.push(entry.unwrap().path());
+            }
+            got.sort();
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn entry_depth() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let d2 = d1.join("d2");
+            let f3 = d2.join("f3.txt");
+
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(f3, []).await?;
+
+            let want = vec![(d1, 0), (d2, 1), (f2, 1), (f1, 0)];
+
+            let mut wd = WalkDir::new(root.path()).filter(|entry| async move {
+                if entry.depth() > 1 {
+                    Filtering::Ignore
+                } else {
+                    Filtering::Continue
+                }
+            });
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                let entry = entry.unwrap();
+                got.push((entry.path(), entry.depth()));
+            }
+            got.sort();
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follow_links() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let d1 = root.path().join("d1");
+            let f1 = d1.join("f1.txt");
+            let link = root.path().join("link");
+            let cycle = d1.join("cycle");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::unix::symlink(&d1, &link).await?;
+            async_fs::unix::symlink(root.path(), &cycle).await?;
+
+            let want = vec![
+                d1.to_owned(),
+                cycle.to_owned(),
+                f1.to_owned(),
+                link.to_owned(),
+            ];
+            let mut wd = WalkDir::new(root.path());
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                got.push(entry.unwrap().path());
+            }
+            got.sort();
+            assert_eq!(got, want);
+
+            let want = vec![d1, f1, link.to_owned(), link.join("f1.txt")];
+            let mut wd = WalkDir::new(root.path()).with_follow_links(true);
+            let mut got = Vec::new();
+            let mut errors = 0;
+            while let Some(entry) = wd.next().await {
+                match entry {
+                    Ok(entry) => got.push(entry.path()),
+                    Err(e) => {
+                        assert_eq!(e.kind(), ErrorKind::Other);
+                        errors += 1;
+                    }
+                }
+            }
+            got.sort();
+            assert_eq!(got, want);
+            assert_eq!(errors, 2);
+
+            Ok(())
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn detect_cycles() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let d1 = root.path().join("d1");
+            let f1 = d1.join("f1.txt");
+            let link = root.path().join("link");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::unix::symlink(&d1, &link).await?;
+
+            let mut wd = WalkDir::new(root.path())
+                .with_follow_links(true)
+                .with_detect_cycles(true);
+            let mut got = Vec::new();
+            let mut errors = 0;
+            while let Some(entry) = wd.next().await {
+                match entry {
+                    Ok(entry) => got.push(entry.path()),
+                    Err(e) => {
+                        assert_eq!(e.kind(), ErrorKind::Other);
+                        errors += 1;
+                    }
+                }
+            }
+            // Whichever of `d1` or `link` comes first is traversed.
+            assert_eq!(got.len(), 2);
+            assert_eq!(errors, 1);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn same_file_system() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+
+            let want = vec![d1, f2, f1];
+
+            let mut wd = WalkDir::new(root.path()).with_same_file_system(true);
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                got.push(entry.unwrap().path());
+            }
+            got.sort();
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn contents_first() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let d2 = d1.join("d2");
+            let f3 = d2.join("f3.txt");
+
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let mut wd = WalkDir::new(root.path()).with_contents_first(true);
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                got.push(entry.unwrap().path());
+            }
+            assert_eq!(got.len(), 5);
+            for (i, path) in got.iter().enumerate() {
+                // A directory never comes before its content.
+                for previous in &got[..i] {
+                    assert!(!path.starts_with(previous));
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn filter_stop() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let d1 = root.path().join("d1");
+            let d2 = d1.join("d2");
+            let f1 = d2.join("f1.txt");
+            let f2 = d2.join("f2.txt");
+
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+
+            let mut wd = WalkDir::new(root.path()).filter(|entry| async move {
+                if entry.file_type().is_ok_and(|ft| ft.is_file()) {
+                    Filtering::Stop
+                } else {
+                    Filtering::Continue
+                }
+            });
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                got.push(entry.unwrap().path());
+            }
+            assert_eq!(got.len(), 3);
+            assert!(got[2] == f1 || got[2] == f2);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn and_filter() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let d2 = d1.join("d2");
+            let f3 = d2.join("f3.txt");
+
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let want = vec![f2.to_owned(), f1.to_owned()];
+
+            let d2_filtered = Arc::new(AtomicBool::new(false));
+            let filtered = d2_filtered.clone();
+            let mut wd = WalkDir::new(root.path())
+                .filter(move |entry| {
+                    let d2 = d2.clone();
+                    async move {
+                        if entry.path() == d2 {
+                            Filtering::IgnoreDir
+                        } else {
+                            Filtering::Continue
+                        }
+                    }
+                })
+                .and_filter(move |entry| {
+                    let filtered = filtered.clone();
+                    async move {
+                        if entry.file_name() == "d2" {
+                            filtered.store(true, Ordering::SeqCst);
+                        }
+                        match entry.file_type() {
+                            Ok(ft) if ft.is_dir() => Filtering::Ignore,
+                            _ => Filtering::Continue,
+                        }
+                    }
+                });
+
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                got.push(entry.unwrap().path());
+            }
+            got.sort();
+            assert_eq!(got, want);
+            assert!(!d2_filtered.load(Ordering::SeqCst));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn filter_with_context() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("src")).await?;
+            async_fs::write(root.path().join("src/lib.rs"), "").await?;
+            async_fs::write(root.path().join("src/notes.txt"), "").await?;
+            async_fs::write(root.path().join("notes.txt"), "").await?;
+
+            let mut got = WalkDir::new(root.path())
+                .filter_with_context(|entry, dir| {
+                    let in_src = dir.file_name().is_some_and(|name| name == "src");
+                    let is_rs = entry.path().extension().is_some_and(|ext| ext == "rs");
+                    async move {
+                        if in_src && !is_rs {
+                            Filtering::Ignore
+                        } else {
+                            Filtering::Continue
+                        }
+                    }
+                })
+                .collect_paths()
+                .await?;
+            got.sort();
+            let want = ["notes.txt", "src", "src/lib.rs"].map(|path| root.path().join(path));
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn filter_entry() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join(".d1");
+            let f2 = d1.join("f2.txt");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+
+            let want = vec![f1];
+
+            let mut wd = WalkDir::new(root.path()).filter_entry(|entry| {
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    Filtering::IgnoreDir
+                } else {
+                    Filtering::Continue
+                }
+            });
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                got.push(entry.unwrap().path());
+            }
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn max_open() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let d2 = d1.join("d2");
+            let f3 = d2.join("f3.txt");
+
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let want = vec![d1, d2, f3, f2, f1];
+
+            let mut wd = WalkDir::new(root.path()).with_max_open(1);
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                got.push(entry.unwrap().path());
+            }
+            got.sort();
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn buffer_size() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let mut want = Vec::new();
+            for dir in ["a", "a/b", "c"] {
+                let dir = root.path().join(dir);
+                async_fs::create_dir_all(&dir).await?;
+                want.push(dir.clone());
+                for i in 0..5 {
+                    let file = dir.join(format!("f{}.txt", i));
+                    async_fs::write(&file, "").await?;
+                    want.push(file);
+                }
+            }
+            want.sort();
+
+            for max_open in [None, Some(1)] {
+                let mut wd = WalkDir::new(root.path()).with_buffer_size(2);
+                if let Some(max_open) = max_open {
+                    wd = wd.with_max_open(max_open);
+                }
+                let mut got = wd.collect_paths().await?;
+                got.sort();
+                assert_eq!(got, want);
+            }
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn walk_multi() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let d1 = root.path().join("d1");
+            let f1 = d1.join("f1.txt");
+            let d2 = root.path().join("d2");
+            let f2 = d2.join("f2.txt");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+
+            let mut wd = WalkDir::new_multi(vec![d2.to_owned(), root.path().join("foobar"), d1]);
+            match wd.next().await.unwrap() {
+                Ok(entry) => assert_eq!(entry.path(), f2),
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+            match wd.next().await.unwrap() {
+                Ok(_) => panic!("want error"),
+                Err(e) => assert_eq!(e.kind(), ErrorKind::NotFound),
+            }
+            match wd.next().await.unwrap() {
+                Ok(entry) => assert_eq!(entry.path(), f1),
+                Err(e) => panic!("unexpected error: {}", e),
+            }
+            assert!(wd.next().await.is_none());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn into_channel() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for i in 0..5 {
+                async_fs::write(root.path().join(format!("f{}.txt", i)), "").await?;
+            }
+
+            let receiver = WalkDir::new(root.path()).into_channel(2);
+            let mut count = 0;
+            while let Ok(entry) = receiver.recv().await {
+                assert!(entry?.file_type()?.is_file());
+                count += 1;
+            }
+            assert_eq!(count, 5);
+
+            let receiver = WalkDir::new(root.path()).into_channel(1);
+            assert!(receiver.recv().await.is_ok());
+            drop(receiver);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn collect_paths() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+
+            let want = vec![d1, f2, f1];
+
+            let mut got = WalkDir::new(root.path()).collect_paths().await?;
+            got.sort();
+            assert_eq!(got, want);
+
+            let err = WalkDir::new("foobar").collect_paths().await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::NotFound);
+
+            let (mut got, errors) =
+                WalkDir::new_multi(vec![root.path().join("foobar"), root.path().to_owned()])
+                    .collect_paths_lossy()
+                    .await;
+            got.sort();
+            assert_eq!(got, want);
+            assert_eq!(errors.len(), 1);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn first_n() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for i in 0..5 {
+                async_fs::write(root.path().join(format!("f{}.txt", i)), "").await?;
+            }
+
+            let got = WalkDir::new(root.path())
+                .with_sort_by_name()
+                .first_n(2)
+                .await?;
+            let got: Vec<_> = got.iter().map(|entry| entry.path()).collect();
+            assert_eq!(
+                got,
+                [root.path().join("f0.txt"), root.path().join("f1.txt")]
+            );
+
+            assert_eq!(WalkDir::new(root.path()).first_n(10).await?.len(), 5);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn count() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+
+            assert_eq!(WalkDir::new(root.path()).count().await?, 3);
+            let err = WalkDir::new("foobar").count().await.unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::NotFound);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn walk_dir_options() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let d2 = d1.join("d2");
+            let f3 = d2.join("f3.txt");
+
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let want = vec![d2, f2];
+
+            let options = WalkDirOptions {
+                max_depth: Some(1),
+                min_depth: 1,
+                ..Default::default()
+            };
+            let mut got = WalkDir::new_with_options(root.path(), options)
+                .collect_paths()
+                .await?;
+            got.sort();
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn error_path() {
+        block_on(async {
+            let mut wd = WalkDir::new("foobar");
+            let err = wd.next().await.unwrap().unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::NotFound);
+            assert_eq!(err.path(), Path::new("foobar"));
+            assert!(err.to_string().starts_with("walking foobar: "));
+        })
+    }
+
+    #[test]
+    fn skip_errors() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            async_fs::write(&f1, []).await?;
+
+            let roots = vec![root.path().join("foobar"), root.path().to_owned()];
+            let mut wd = WalkDir::new_multi(roots.clone()).skip_errors();
+            assert_eq!(wd.next().await.unwrap().path(), f1);
+            assert!(wd.next().await.is_none());
+
+            let errors = Arc::new(Mutex::new(Vec::new()));
+            let errs = errors.clone();
+            let mut wd = WalkDir::new_multi(roots).on_error(move |e| {
+                let errs = errs.clone();
+                async move { errs.lock().unwrap().push(e.path().to_owned()) }
+            });
+            assert_eq!(wd.next().await.unwrap().path(), f1);
+            assert!(wd.next().await.is_none());
+            assert_eq!(*errors.lock().unwrap(), vec![root.path().join("foobar")]);
+
+            Ok(())
+        })
+    }
+
+    #[cfg(feature = "glob")]
+    #[test]
+    fn filter_glob() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.rs");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let f3 = d1.join("f3.rs");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let want = vec![f3, f1];
+
+            let mut got = WalkDir::new(root.path())
+                .filter_glob("*.rs")
+                .unwrap()
+                .collect_paths()
+                .await?;
+            got.sort();
+            assert_eq!(got, want);
+
+            assert!(WalkDir::new(root.path()).filter_glob("[").is_err());
+
+            Ok(())
+        })
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn filter_regex() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.rs");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let f3 = d1.join("f3.rs");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let want = vec![f3, f1];
+
+            let mut got = WalkDir::new(root.path())
+                .filter_regex(r"\.rs$")
+                .unwrap()
+                .collect_paths()
+                .await?;
+            got.sort();
+            assert_eq!(got, want);
+
+            assert!(WalkDir::new(root.path()).filter_regex("(").is_err());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn filter_extension() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.rs");
+            let d1 = root.path().join("d1.rs");
+            let f2 = d1.join("f2.txt");
+            let f3 = d1.join("f3.rs");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let want = vec![f3, f1];
+
+            let mut got = WalkDir::new(root.path())
+                .filter_extension("rs")
+                .collect_paths()
+                .await?;
+            got.sort();
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn filter_hidden() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let f2 = root.path().join(".f2.txt");
+            let d1 = root.path().join(".d1");
+            let f3 = d1.join("f3.txt");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let want = vec![f1];
+
+            let got = WalkDir::new(root.path())
+                .filter_hidden()
+                .collect_paths()
+                .await?;
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn filter_size() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let f3 = d1.join("f3.txt");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::write(&f1, [0; 10]).await?;
+            async_fs::write(&f2, [0; 100]).await?;
+            async_fs::write(&f3, [0; 1000]).await?;
+
+            let mut got = WalkDir::new(root.path())
+                .filter_size_range(10, 100)
+                .collect_paths()
+                .await?;
+            got.sort();
+            assert_eq!(got, vec![d1.to_owned(), f2.to_owned(), f1.to_owned()]);
+
+            let mut got = WalkDir::new(root.path())
+                .filter_min_size(100)
+                .collect_paths()
+                .await?;
+            got.sort();
+            assert_eq!(got, vec![d1.to_owned(), f2, f3]);
+
+            let mut got = WalkDir::new(root.path())
+                .filter_max_size(50)
+                .collect_paths()
+                .await?;
+            got.sort();
+            assert_eq!(got, vec![d1, f1]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn filter_modified() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            let modified = async_fs::metadata(&f2).await?.modified()?;
+            let before = modified - Duration::from_secs(60);
+            let after = modified + Duration::from_secs(60);
+
+            let mut got = WalkDir::new(root.path())
+                .filter_modified_after(before)
+                .filter_modified_before(after)
+                .collect_paths()
+                .await?;
+            got.sort();
+            assert_eq!(got, vec![d1.to_owned(), f2, f1]);
+
+            let got = WalkDir::new(root.path())
+                .filter_modified_after(after)
+                .collect_paths()
+                .await?;
+            assert_eq!(got, vec![d1]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn filter_ignore_and_stop() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let d1 = root.path().join("d1");
+            let d2 = d1.join("d2");
+            let f1 = d2.join("f1.txt");
+            let f2 = d2.join("f2.txt");
+
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+
+            let want = vec![d1, d2];
+
+            let got = WalkDir::new(root.path())
+                .filter_entry(|entry| {
+                    if entry.file_type().is_ok_and(|ft| ft.is_file()) {
+                        Filtering::IgnoreAndStop
+                    } else {
+                        Filtering::Continue
+                    }
+                })
+                .collect_paths()
+                .await?;
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn breadth_first() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let d2 = d1.join("d2");
+            let f3 = d2.join("f3.txt");
+
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let mut wd = WalkDir::new(root.path()).with_breadth_first(true);
+            let mut got = Vec::new();
+            while let Some(entry) = wd.next().await {
+                let entry = entry.unwrap();
+                got.push((entry.depth(), entry.path()));
+            }
+            assert!(got.windows(2).all(|w| w[0].0 <= w[1].0));
+            got.sort();
+            assert_eq!(got, vec![(0, d1), (0, f1), (1, d2), (1, f2), (2, f3)]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn ordered_by_depth() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for i in 0..4 {
+                let dir = root.path().join(format!("d{}", i));
+                async_fs::create_dir_all(dir.join("a/b")).await?;
+                async_fs::write(dir.join("a/b/f.txt"), "").await?;
+                async_fs::write(dir.join("f.txt"), "").await?;
+            }
+
+            let mut wd = WalkDir::new(root.path())
+                .with_ordered_by_depth(true)
+                .with_parallelism(3);
+            let mut depths = Vec::new();
+            while let Some(entry) = wd.next().await {
+                depths.push(entry?.depth());
+            }
+            assert_eq!(depths.len(), 20);
+            assert!(depths.windows(2).all(|w| w[0] <= w[1]));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn with_open_dir_fn() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a/b")).await?;
+            async_fs::write(root.path().join("a/f.txt"), b"").await?;
+            async_fs::write(root.path().join("a/b/g.txt"), b"").await?;
+
+            let opened = Arc::new(Mutex::new(Vec::new()));
+            let open_dir: OpenDirFn = {
+                let opened = opened.clone();
+                Arc::new(move |path: &Path| {
+                    opened.lock().unwrap().push(path.to_owned());
+                    if path.ends_with("b") {
+                        return Err(std::io::Error::other("denied"));
+                    }
+                    std::fs::read_dir(path)
+                })
+            };
+            let mut wd = WalkDir::new(root.path()).with_open_dir_fn(open_dir);
+            let mut got = Vec::new();
+            let mut errors = Vec::new();
+            while let Some(entry) = wd.next().await {
+                match entry {
+                    Ok(entry) => got.push(entry.path()),
+                    Err(e) => errors.push(e.path().to_owned()),
+                }
+            }
+            got.sort();
+            let want = ["a", "a/f.txt"].map(|name| root.path().join(name));
+            assert_eq!(got, want);
+            assert_eq!(errors, [root.path().join("a/b")]);
+
+            let mut opened = opened.lock().unwrap().clone();
+            opened.sort();
+            let want = ["", "a", "a/b"].map(|name| root.path().join(name));
+            assert_eq!(opened, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn virtual_walk_dir() {
+        block_on(async {
+            let mut tree = VirtualTree::new();
+            tree.insert(
+                "/r".into(),
+                vec![
+                    ("a".into(), VirtualFileType::Dir),
+                    ("f.txt".into(), VirtualFileType::File),
+                    ("e".into(), VirtualFileType::Dir),
+                ],
+            );
+            tree.insert(
+                "/r/a".into(),
+                vec![
+                    ("g.txt".into(), VirtualFileType::File),
+                    ("l".into(), VirtualFileType::Symlink),
+                ],
+            );
+
+            let got: Vec<_> = VirtualWalkDir::new(tree.clone(), "/r")
+                .map(|entry| {
+                    let entry = entry.unwrap();
+                    (entry.path(), entry.depth(), entry.file_type())
+                })
+                .collect()
+                .await;
+            let want = [
+                ("/r/a", 0, VirtualFileType::Dir),
+                ("/r/a/g.txt", 1, VirtualFileType::File),
+                ("/r/a/l", 1, VirtualFileType::Symlink),
+                ("/r/f.txt", 0, VirtualFileType::File),
+                ("/r/e", 0, VirtualFileType::Dir),
+            ]
+            .map(|(path, depth, file_type)| (path.into(), depth, file_type));
+            assert_eq!(got, want);
+
+            let mut wd = VirtualWalkDir::new(tree.clone(), "/missing");
+            let err = wd.next().await.unwrap().unwrap_err();
+            assert_eq!(err.io_error().kind(), ErrorKind::NotFound);
+            assert!(wd.next().await.is_none());
+
+            // Names leading out of their directory are rejected.
+            let names = ["..", "/r", "a/g.txt", ""];
+            tree.insert(
+                "/r/e".into(),
+                names.map(|name| (name.into(), VirtualFileType::Dir)).into(),
+            );
+            let got: Vec<_> = VirtualWalkDir::new(tree, "/r/e").collect().await;
+            assert_eq!(got.len(), names.len());
+            for item in got {
+                let err = item.unwrap_err();
+                assert_eq!(err.io_error().kind(), ErrorKind::InvalidInput);
+                assert_eq!(err.path(), Path::new("/r/e"));
+            }
+        })
+    }
+
+    #[test]
+    fn try_clone() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a/b")).await?;
+            async_fs::write(root.path().join("a/f.txt"), b"").await?;
+
+            let mut wd = WalkDir::new(root.path()).with_max_depth(1);
+            let clone = wd.try_clone().expect("nothing was walked");
+            let mut got = clone.collect_paths().await?;
+            got.sort();
+            assert_eq!(
+                got,
+                [
+                    root.path().join("a"),
+                    root.path().join("a/b"),
+                    root.path().join("a/f.txt")
+                ]
+            );
+
+            // Both walks yield the remaining entries in the same order.
+            wd.next().await.unwrap()?;
+            let clone = wd.try_clone().expect("no closure is set");
+            let got = clone.collect_paths().await?;
+            assert_eq!(got.len(), 2);
+            assert_eq!(got, wd.collect_paths().await?);
+
+            // Branched in the middle of a directory read by batches.
+            for name in ["a/g1.txt", "a/g2.txt", "a/g3.txt"] {
+                async_fs::write(root.path().join(name), b"").await?;
+            }
+            let mut wd = WalkDir::new(root.path()).with_buffer_size(2);
+            wd.next().await.unwrap()?;
+            wd.next().await.unwrap()?;
+            let clone = wd.try_clone().expect("no closure is set");
+            let got = clone.collect_paths().await?;
+            assert_eq!(got.len(), 4);
+            assert_eq!(got, wd.collect_paths().await?);
+
+            let mut wd = WalkDir::new(root.path()).with_parallelism(2);
+            wd.next().await.unwrap()?;
+            let mut got = wd.try_clone().unwrap().collect_paths().await?;
+            got.sort();
+            let mut want = wd.collect_paths().await?;
+            want.sort();
+            assert_eq!(got, want);
+
+            // The closures are shared with the clone.
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let mut wd = WalkDir::new(root.path())
+                .filter_extension("txt")
+                .with_sort_by(|a, b| b.file_name().cmp(&a.file_name()))
+                .with_cancellation_token(cancelled.clone());
+            wd.next().await.unwrap()?;
+            let clone = wd.try_clone().expect("no entry is being read");
+            let got = clone.collect_paths().await?;
+            let want = ["a/g2.txt", "a/g1.txt", "a/f.txt"].map(|name| root.path().join(name));
+            assert_eq!(got, want);
+            cancelled.store(true, Ordering::Relaxed);
+            assert!(wd.try_clone().unwrap().next().await.is_none());
+
+            // The contents first entries are read again by the clone.
+            let mut wd = WalkDir::new(root.path()).with_contents_first(true);
+            wd.next().await.unwrap()?;
+            let got = wd.try_clone().unwrap().collect_paths().await?;
+            assert_eq!(got, wd.collect_paths().await?);
+            assert_eq!(got.last(), Some(&root.path().join("a")));
+
+            let mut wd = WalkDir::new(root.path()).chain(WalkDir::new(root.path()));
+            assert!(wd.try_clone().is_none());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn builder() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a/b")).await?;
+            async_fs::write(root.path().join("a/f.txt"), b"").await?;
+            async_fs::write(root.path().join("g.rs"), b"").await?;
+
+            let builder = WalkDirBuilder::new()
+                .with_max_depth(1)
+                .configure(|wd| wd.filter_extension("txt"));
+            for _ in 0..2 {
+                let got = builder.build(root.path())?.collect_paths().await?;
+                assert_eq!(got, [root.path().join("a/f.txt")]);
+            }
+
+            let err = match WalkDirBuilder::new()
+                .with_min_depth(2)
+                .with_max_depth(1)
+                .build(root.path())
+            {
+                Ok(_) => panic!("the depths conflict"),
+                Err(e) => e,
+            };
+            assert_eq!(err.io_error().kind(), ErrorKind::InvalidInput);
+            assert_eq!(err.path(), root.path());
+            let builder = WalkDirBuilder::new().configure(|wd| wd.with_min_file_size(2));
+            assert!(builder.clone().build(root.path()).is_ok());
+            assert!(builder.with_max_file_size(1).build(root.path()).is_err());
+            assert!(WalkDirBuilder::new()
+                .with_contents_first(true)
+                .with_breadth_first(true)
+                .build(root.path())
+                .is_err());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn chain() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a/b")).await?;
+            async_fs::create_dir_all(root.path().join("c")).await?;
+            async_fs::write(root.path().join("c/f.txt"), b"").await?;
+
+            let got = WalkDir::new(root.path().join("a"))
+                .chain(WalkDir::new(root.path().join("c")))
+                .collect_paths()
+                .await?;
+            let want = ["a/b", "c/f.txt"].map(|name| root.path().join(name));
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn interleave() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for name in ["a/f1", "a/f2", "a/f3", "b/g1"] {
+                let path = root.path().join(name);
+                async_fs::create_dir_all(path.parent().unwrap()).await?;
+                async_fs::write(path, b"").await?;
+            }
+
+            let got = WalkDir::new(root.path().join("a"))
+                .with_sort_by_name()
+                .interleave(WalkDir::new(root.path().join("b")))
+                .collect_paths()
+                .await?;
+            let want = ["a/f1", "b/g1", "a/f2", "a/f3"].map(|name| root.path().join(name));
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn exclude_path() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for name in ["a/b/f1", "a/f2", "c/f3", "f4"] {
+                let path = root.path().join(name);
+                async_fs::create_dir_all(path.parent().unwrap()).await?;
+                async_fs::write(path, b"").await?;
+            }
+
+            let mut got = WalkDir::new(root.path())
+                .exclude_path("a/b")
+                .exclude_path(root.path().join("c"))
+                .exclude_path("f4")
+                .collect_paths()
+                .await?;
+            got.sort();
+            let want = ["a", "a/f2"].map(|name| root.path().join(name));
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn with_included_paths() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for name in ["a/b/c/f1", "a/b/f2", "a/f3", "d/f4", "e/f5"] {
+                let path = root.path().join(name);
+                async_fs::create_dir_all(path.parent().unwrap()).await?;
+                async_fs::write(path, b"").await?;
+            }
+
+            let mut got = WalkDir::new(root.path())
+                .with_included_paths(vec!["a/b/c".into(), root.path().join("d")])
+                .collect_paths()
+                .await?;
+            got.sort();
+            let want = ["a/b/c", "a/b/c/f1", "d", "d/f4"].map(|name| root.path().join(name));
+            assert_eq!(got, want);
+
+            let got = WalkDir::new(root.path())
+                .with_included_paths(Vec::new())
+                .collect_paths()
+                .await?;
+            assert!(got.is_empty());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn difference() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let (a, b) = (root.path().join("a"), root.path().join("b"));
+            let mtime = SystemTime::now() - Duration::from_secs(3600);
+            for (name, content) in [
+                ("a/same", "x"),
+                ("b/same", "x"),
+                ("a/changed", "x"),
+                ("b/changed", "xy"),
+                ("a/d/only_a", ""),
+                ("b/only_b", ""),
+            ] {
+                let path = root.path().join(name);
+                async_fs::create_dir_all(path.parent().unwrap()).await?;
+                std::fs::write(&path, content)?;
+                std::fs::File::options()
+                    .write(true)
+                    .open(&path)?
+                    .set_modified(mtime)?;
+            }
+
+            let got: Vec<_> = WalkDir::difference(&a, &b)
+                .map(|diff| match diff.unwrap() {
+                    DiffEntry::OnlyInA(path) => ("a", path),
+                    DiffEntry::OnlyInB(path) => ("b", path),
+                    DiffEntry::Modified {
+                        path,
+                        a_meta,
+                        b_meta,
+                    } => {
+                        assert_eq!((a_meta.len(), b_meta.len()), (1, 2));
+                        ("modified", path)
+                    }
+                })
+                .collect()
+                .await;
+            let want = [
+                ("modified", "changed"),
+                ("a", "d"),
+                ("a", "d/only_a"),
+                ("b", "only_b"),
+            ]
+            .map(|(side, path)| (side, PathBuf::from(path)));
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn with_timeout() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::write(root.path().join("f.txt"), b"").await?;
+
+            let mut wd = WalkDir::new(root.path()).with_timeout(Duration::ZERO, |_| async {});
+            let err = wd.next().await.unwrap().unwrap_err();
+            assert_eq!(err.io_error().kind(), ErrorKind::TimedOut);
+            assert_eq!(err.path(), root.path());
+            assert!(wd.next().await.is_none());
+
+            // The timer is started when the stream is first polled.
+            let started = Arc::new(AtomicUsize::new(0));
+            let got = WalkDir::new(root.path())
+                .with_timeout(Duration::from_secs(60), {
+                    let started = started.clone();
+                    move |duration| {
+                        assert_eq!(duration, Duration::from_secs(60));
+                        started.fetch_add(1, Ordering::Relaxed);
+                        future::pending::<()>()
+                    }
+                })
+                .collect_paths()
+                .await?;
+            assert_eq!(got, [root.path().join("f.txt")]);
+            assert_eq!(started.load(Ordering::Relaxed), 1);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn reversed_fs_order() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for name in ["a/f1", "a/f2", "a/f3", "f4"] {
+                let path = root.path().join(name);
+                async_fs::create_dir_all(path.parent().unwrap()).await?;
+                async_fs::write(path, b"").await?;
+            }
+
+            let mut want = WalkDir::new(root.path().join("a")).collect_paths().await?;
+            want.reverse();
+            let got = WalkDir::new(root.path().join("a"))
+                .with_reversed_fs_order()
+                .collect_paths()
+                .await?;
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn try_for_each() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for name in ["f1", "f2", "f3"] {
+                async_fs::write(root.path().join(name), b"").await?;
+            }
+
+            let mut got = Vec::new();
+            WalkDir::new(root.path())
+                .try_for_each(|entry| {
+                    got.push(entry.path());
+                    async { Ok(()) }
+                })
+                .await?;
+            got.sort();
+            assert_eq!(got, ["f1", "f2", "f3"].map(|name| root.path().join(name)));
+
+            let mut calls = 0;
+            let err = WalkDir::new(root.path())
+                .try_for_each(|_| {
+                    calls += 1;
+                    async { Err(std::io::Error::other("failed")) }
+                })
+                .await
+                .unwrap_err();
+            assert_eq!(calls, 1);
+            assert_eq!(err.path().parent(), Some(root.path()));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn entry_hook() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for name in ["f1", "f2", "g1"] {
+                async_fs::write(root.path().join(name), b"").await?;
+            }
+
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let count = Arc::new(AtomicUsize::new(0));
+            let (s, c) = (seen.clone(), count.clone());
+            let mut got = WalkDir::new(root.path())
+                .filter(|entry| async move {
+                    match entry.file_name().to_string_lossy().starts_with('f') {
+                        true => Filtering::Continue,
+                        false => Filtering::Ignore,
+                    }
+                })
+                .with_entry_hook(move |entry| s.lock().unwrap().push(entry.path()))
+                .with_entry_hook(move |_| {
+                    c.fetch_add(1, Ordering::Relaxed);
+                })
+                .collect_paths()
+                .await?;
+            let mut seen = seen.lock().unwrap().clone();
+            got.sort();
+            seen.sort();
+            assert_eq!(got, ["f1", "f2"].map(|name| root.path().join(name)));
+            assert_eq!(seen, got);
+            assert_eq!(count.load(Ordering::Relaxed), 2);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn dir_hooks() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a/b")).await?;
+            async_fs::write(root.path().join("a/f.txt"), b"").await?;
+
+            let events = Arc::new(Mutex::new(Vec::new()));
+            let (enter, exit, entry) = (events.clone(), events.clone(), events.clone());
+            WalkDir::new(root.path())
+                .with_sort_by_name()
+                .with_dir_enter_hook(move |path| {
+                    enter.lock().unwrap().push(("enter", path.to_owned()))
+                })
+                .with_dir_exit_hook(move |path| {
+                    exit.lock().unwrap().push(("exit", path.to_owned()))
+                })
+                .with_entry_hook(move |e| entry.lock().unwrap().push(("entry", e.path())))
+                .collect_paths()
+                .await?;
+            let got = events.lock().unwrap().clone();
+            let want = [
+                ("enter", ""),
+                ("enter", "a"),
+                ("entry", "a"),
+                ("enter", "a/b"),
+                ("entry", "a/b"),
+                ("exit", "a/b"),
+                ("entry", "a/f.txt"),
+                ("exit", "a"),
+                ("exit", ""),
+            ]
+            .map(|(event, name)| (event, root.path().join(name)));
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn peekable() -> Result<()> {
+        use futures_core::stream::FusedStream;
+
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::write(root.path().join("f.txt"), b"").await?;
+
+            let mut wd = WalkDir::new(root.path()).peekable();
+            let peeked = wd.peek().await.unwrap().as_ref().unwrap().path();
+            assert_eq!(peeked, root.path().join("f.txt"));
+            assert_eq!(wd.peek().await.unwrap().as_ref().unwrap().path(), peeked);
+            assert_eq!(wd.next().await.unwrap()?.path(), peeked);
+            assert!(wd.peek().await.is_none());
+            assert!(wd.is_terminated());
+            assert!(wd.next().await.is_none());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn max_io_errors_per_dir() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for name in ["a/b1", "a/b2", "a/b3", "a/f", "c/f"] {
+                async_fs::create_dir_all(root.path().join(name)).await?;
+            }
+
+            let open_dir: OpenDirFn = Arc::new(|path: &Path| {
+                match path.file_name().unwrap().to_string_lossy().starts_with('b') {
+                    true => Err(std::io::Error::new(ErrorKind::TimedOut, "unreachable")),
+                    false => std::fs::read_dir(path),
+                }
+            });
+            let mut wd = WalkDir::new(root.path())
+                .with_sort_by_name()
+                .with_open_dir_fn(open_dir)
+                .with_max_io_errors_per_dir(2);
+            let mut got = Vec::new();
+            while let Some(item) = wd.next().await {
+                match item {
+                    Ok(entry) => got.push(("ok", entry.path(), None)),
+                    Err(e) => got.push(("err", e.path().to_owned(), Some(e.io_error().kind()))),
+                }
+            }
+            let want = [
+                ("ok", "a", None),
+                ("err", "a/b1", Some(ErrorKind::TimedOut)),
+                ("err", "a", Some(ErrorKind::TimedOut)),
+                ("ok", "c", None),
+                ("ok", "c/f", None),
+            ]
+            .map(|(item, name, kind)| (item, root.path().join(name), kind));
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn from_read_dir() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a")).await?;
+            async_fs::write(root.path().join("a/f1.txt"), "").await?;
+
+            let rd = std::fs::read_dir(root.path())?;
+            let mut got = WalkDir::from_read_dir(rd, root.path())
+                .collect_paths()
+                .await?;
+            got.sort();
+            assert_eq!(got, [root.path().join("a"), root.path().join("a/f1.txt")]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn rate_limit() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for i in 0..130 {
+                async_fs::write(root.path().join(format!("f{}.txt", i)), "").await?;
+            }
+
+            let slept = Arc::new(Mutex::new(Vec::new()));
+            let count = WalkDir::new(root.path())
+                .with_rate_limit(100.0, {
+                    let slept = slept.clone();
+                    move |delay| {
+                        slept.lock().unwrap().push(delay);
+                        future::ready(())
+                    }
+                })
+                .count()
+                .await?;
+            assert_eq!(count, 130);
+            // Only the entries past the burst are delayed.
+            let slept = slept.lock().unwrap().len();
+            assert!(slept > 0 && slept <= 30);
+
+            // 30 entries at 100 per second, after a burst of 100 entries.
+            let mut limiter = RateLimiter::new(100.0);
+            let now = std::time::Instant::now();
+            let delays: Vec<_> = (0..130).map(|_| limiter.acquire(now)).collect();
+            assert!(delays[..100].iter().all(Option::is_none));
+            assert_eq!(delays[100], Some(Duration::from_millis(10)));
+            assert_eq!(delays[129], Some(Duration::from_millis(300)));
+
+            let mut limiter = RateLimiter::new(1e-300);
+            assert_eq!(limiter.acquire(now), None);
+            assert_eq!(limiter.acquire(now), Some(Duration::MAX));
+
+            Ok(())
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_limit() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("d1")).await?;
+            async_fs::unix::symlink("d1", root.path().join("l1")).await?;
+            async_fs::unix::symlink("l1", root.path().join("l2")).await?;
+
+            let mut got = WalkDir::new(root.path())
+                .with_follow_links(true)
+                .with_symlink_limit(2)
+                .collect_paths()
+                .await?;
+            got.sort();
+            let want = ["d1", "l1", "l2"].map(|name| root.path().join(name));
+            assert_eq!(got, want);
+
+            let (got, errors) = WalkDir::new(root.path())
+                .with_follow_links(true)
+                .with_symlink_limit(1)
+                .collect_paths_lossy()
+                .await;
+            assert_eq!(got.len(), 2);
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].path(), root.path().join("l2"));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn try_filter() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let d1 = root.path().join("d1");
+            let f1 = d1.join("f1.txt");
+            let d2 = root.path().join("d2");
+            let f2 = d2.join("f2.txt");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+
+            let filter = |entry: Arc<Entry>| async move {
+                if entry.file_name() == "d1" {
+                    Err(std::io::Error::other("bad directory"))
+                } else {
+                    Ok(Filtering::Continue)
+                }
+            };
+
+            let (mut got, errors) = WalkDir::new(root.path())
+                .try_filter(filter)
+                .collect_paths_lossy()
+                .await;
+            got.sort();
+            assert_eq!(got, vec![d2, f2]);
+            assert_eq!(errors.len(), 1);
+            assert_eq!(errors[0].path(), d1);
+
+            let (got, errors) = WalkDir::new(root.path())
+                .try_filter(filter)
+                .with_filter_error_policy(FilterErrorPolicy::Stop)
+                .collect_paths_lossy()
+                .await;
+            assert!(got.len() <= 2);
+            assert_eq!(errors.len(), 1);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn into_path_stream() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            async_fs::write(&f1, []).await?;
+
+            let mut paths = WalkDir::new(root.path()).into_path_stream();
+            assert_eq!(paths.next().await.unwrap()?, f1);
+            assert!(paths.next().await.is_none());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn into_dir_and_file_stream() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let d2 = d1.join("d2");
+
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+
+            let mut dirs = WalkDir::new(root.path()).into_dir_stream();
+            let mut got = Vec::new();
+            while let Some(entry) = dirs.next().await {
+                got.push(entry.unwrap().path());
+            }
+            got.sort();
+            assert_eq!(got, vec![d1, d2]);
+
+            let mut files = WalkDir::new(root.path()).into_file_stream();
+            let mut got = Vec::new();
+            while let Some(entry) = files.next().await {
+                got.push(entry.unwrap().path());
+            }
+            got.sort();
+            assert_eq!(got, vec![f2, f1]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn stats() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::write(&f1, [0; 10]).await?;
+            async_fs::write(&f2, [0; 20]).await?;
+
+            let (wd, stats) =
+                WalkDir::new_multi(vec![root.path().to_owned(), root.path().join("foobar")])
+                    .filter_extension("txt")
+                    .with_stats();
+            let (got, _) = wd.collect_paths_lossy().await;
+            assert_eq!(got.len(), 2);
+            assert_eq!(stats.entries_visited(), 3);
+            assert_eq!(stats.dirs_visited(), 2);
+            assert_eq!(stats.errors_encountered(), 1);
+            assert_eq!(stats.bytes_examined(), 30);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn for_each_concurrent() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+
+            let running = Arc::new(AtomicUsize::new(0));
+            let paths = Arc::new(Mutex::new(Vec::new()));
+            WalkDir::new(root.path())
+                .for_each_concurrent(2, |entry| {
+                    let running = running.clone();
+                    let paths = paths.clone();
+                    async move {
+                        assert!(running.fetch_add(1, Ordering::SeqCst) < 2);
+                        futures_lite::future::yield_now().await;
+                        paths.lock().unwrap().push(entry.unwrap().path());
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    }
+                })
+                .await;
+            let mut got = paths.lock().unwrap().clone();
+            got.sort();
+            assert_eq!(got, vec![d1, f2, f1]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn sort_by() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let d1 = root.path().join("d1");
+            let f2 = d1.join("f2.txt");
+            let f3 = d1.join("f3.txt");
+            let d2 = root.path().join("d2");
+
+            async_fs::create_dir_all(&d1).await?;
+            async_fs::create_dir_all(&d2).await?;
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let want = vec![f1, d2, d1, f3, f2];
+
+            let got = WalkDir::new(root.path())
+                .with_sort_by(|a, b| b.file_name().cmp(&a.file_name()))
+                .collect_paths()
+                .await?;
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn sort_by_name() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("a.txt");
+            let f2 = root.path().join("B.txt");
+            let f3 = root.path().join("c.txt");
+
+            async_fs::write(&f1, []).await?;
+            async_fs::write(&f2, []).await?;
+            async_fs::write(&f3, []).await?;
+
+            let got = WalkDir::new(root.path())
+                .with_sort_by_name()
+                .collect_paths()
+                .await?;
+            assert_eq!(got, vec![f2.to_owned(), f1.to_owned(), f3.to_owned()]);
+
+            let got = WalkDir::new(root.path())
+                .with_sort_by_name_case_insensitive()
+                .collect_paths()
+                .await?;
+            assert_eq!(got, vec![f1, f2, f3]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn sort_natural() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for name in [
+                "f10.txt",
+                "f2.txt",
+                "f02.txt",
+                "f1b.txt",
+                "f1a10.txt",
+                "f1a9.txt",
+                "é1",
+            ] {
+                async_fs::write(root.path().join(name), "").await?;
+            }
+
+            let got = WalkDir::new(root.path())
+                .with_sort_natural()
+                .collect_paths()
+                .await?;
+            let want = [
+                "f1a9.txt",
+                "f1a10.txt",
+                "f1b.txt",
+                "f02.txt",
+                "f2.txt",
+                "f10.txt",
+                "é1",
+            ]
+            .map(|name| root.path().join(name));
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn sort_by_modified_time() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("f1.txt");
+            let f2 = root.path().join("f2.txt");
+            let f3 = root.path().join("f3.txt");
+
+            let now = SystemTime::now();
+            for (path, age) in [(&f1, 20), (&f2, 10), (&f3, 30)] {
+                let file = std::fs::File::create(path)?;
+                file.set_modified(now - Duration::from_secs(age))?;
+            }
+
+            let got = WalkDir::new(root.path())
+                .with_sort_by_modified_time()
+                .collect_paths()
+                .await?;
+            assert_eq!(got, vec![f2, f1, f3]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn find_first() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a/b")).await?;
+            async_fs::write(root.path().join("a/b/target.txt"), "").await?;
+            async_fs::write(root.path().join("a/other.txt"), "").await?;
+
+            let found = WalkDir::new(root.path())
+                .find_first(|entry| async move { entry.file_name() == "target.txt" })
+                .await?;
+            assert_eq!(
+                found.map(|entry| entry.path()),
+                Some(root.path().join("a/b/target.txt"))
+            );
+
+            let found = WalkDir::new(root.path())
+                .find_first(|entry| async move { entry.file_name() == "missing.txt" })
+                .await?;
+            assert!(found.is_none());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    #[cfg(feature = "ignore")]
+    fn gitignore() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("src/generated")).await?;
+            async_fs::create_dir_all(root.path().join("target")).await?;
+            async_fs::write(root.path().join(".gitignore"), "target/\n*.log\n").await?;
+            async_fs::write(root.path().join("src/.gitignore"), "generated\n!keep.log\n").await?;
+            async_fs::write(root.path().join("src/main.rs"), "").await?;
+            async_fs::write(root.path().join("src/keep.log"), "").await?;
+            async_fs::write(root.path().join("src/generated/out.rs"), "").await?;
+            async_fs::write(root.path().join("target/out"), "").await?;
+            async_fs::write(root.path().join("debug.log"), "").await?;
+
+            let mut got = WalkDir::new(root.path())
+                .with_gitignore(true)
+                .collect_paths()
+                .await?;
+            got.sort();
+            let want = vec![
+                root.path().join(".gitignore"),
+                root.path().join("src"),
+                root.path().join("src/.gitignore"),
+                root.path().join("src/keep.log"),
+                root.path().join("src/main.rs"),
+            ];
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    #[cfg(feature = "ignore")]
+    fn ignore_files() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::write(root.path().join(".gitignore"), "*.log\n").await?;
+            async_fs::write(root.path().join(".ignore"), "*.tmp\n!keep.log\n").await?;
+            async_fs::write(root.path().join("debug.log"), "").await?;
+            async_fs::write(root.path().join("keep.log"), "").await?;
+            async_fs::write(root.path().join("cache.tmp"), "").await?;
+
+            let mut got = WalkDir::new(root.path())
+                .with_ignore_files(true)
+                .collect_paths()
+                .await?;
+            got.sort();
+            let want = vec![
+                root.path().join(".gitignore"),
+                root.path().join(".ignore"),
+                root.path().join("debug.log"),
+                root.path().join("keep.log"),
+            ];
+            assert_eq!(got, want);
+
+            let mut got = WalkDir::new(root.path())
+                .with_gitignore(true)
+                .with_ignore_files(true)
+                .collect_paths()
+                .await?;
+            got.sort();
+            let want = vec![
+                root.path().join(".gitignore"),
+                root.path().join(".ignore"),
+                root.path().join("keep.log"),
+            ];
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    #[cfg(feature = "ignore")]
+    fn custom_ignore_file() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("sub")).await?;
+            async_fs::write(root.path().join(".npmignore"), "*.log\n").await?;
+            async_fs::write(root.path().join("sub/.dockerignore"), "*.tmp\n").await?;
+            async_fs::write(root.path().join("sub/debug.log"), "").await?;
+            async_fs::write(root.path().join("sub/cache.tmp"), "").await?;
+            async_fs::write(root.path().join("sub/main.rs"), "").await?;
+
+            let mut got = WalkDir::new(root.path())
+                .with_custom_ignore_file(".npmignore")
+                .with_custom_ignore_file(".dockerignore")
+                .collect_paths()
+                .await?;
+            got.sort();
+            let want = vec![
+                root.path().join(".npmignore"),
+                root.path().join("sub"),
+                root.path().join("sub/.dockerignore"),
+                root.path().join("sub/main.rs"),
+            ];
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn root_entry() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a")).await?;
+
+            let got: Vec<_> = WalkDir::new(root.path())
+                .with_root_entry(true)
+                .map(|item| item.map(|entry| entry.path()))
+                .try_collect()
+                .await?;
+            assert_eq!(got, vec![root.path().to_owned(), root.path().join("a")]);
+
+            let got: Vec<_> = WalkDir::new(root.path())
+                .with_contents_first(true)
+                .with_root_entry(true)
+                .map(|item| item.map(|entry| entry.path()))
+                .try_collect()
+                .await?;
+            assert_eq!(got, vec![root.path().join("a"), root.path().to_owned()]);
+
+            // A root without file name, thus not found in a parent listing.
+            let dotdot = root.path().join("a/..");
+            let mut wd = WalkDir::new(&dotdot).with_root_entry(true);
+            match wd.next().await.unwrap()? {
+                RootedEntry::Root(entry) => {
+                    assert_eq!(entry.path(), dotdot);
+                    assert!(entry.file_type().is_dir());
+                }
+                RootedEntry::Entry(entry) => panic!("unexpected entry {:?}", entry.path()),
+            }
+            let entry = wd.next().await.unwrap()?.into_entry().unwrap();
+            assert_eq!(entry.file_name(), "a");
+            assert!(wd.next().await.is_none());
+
+            // The roots are not yielded unless requested.
+            let got = WalkDir::new(root.path())
+                .with_root_entry(false)
+                .count()
+                .await;
+            assert_eq!(got, 1);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn walk_deep_tree() -> Result<()> {
+        let root = tempfile::tempdir()?;
+        let deep: std::path::PathBuf = std::iter::repeat_n("d", 500).collect();
+        std::fs::create_dir_all(root.path().join(&deep))?;
+
+        // The stack usage of the walk does not grow with the depth.
+        let root_path = root.path().to_owned();
+        let count = std::thread::Builder::new()
+            .stack_size(128 * 1024)
+            .spawn(move || block_on(WalkDir::new(root_path).count()))?
+            .join()
+            .unwrap()?;
+        assert_eq!(count, 500);
+
+        Ok(())
+    }
+
+    #[test]
+    fn parallelism() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let mut want = Vec::new();
+            for i in 0..5 {
+                let dir = root.path().join(format!("d{}", i));
+                async_fs::create_dir_all(dir.join("sub")).await?;
+                async_fs::write(dir.join("sub/f.txt"), "").await?;
+                want.push(dir.clone());
+                want.push(dir.join("sub"));
+                want.push(dir.join("sub/f.txt"));
+            }
+            want.sort();
+
+            let mut got = WalkDir::new(root.path())
+                .with_parallelism(3)
+                .collect_paths()
+                .await?;
+            got.sort();
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn cached_metadata() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::write(root.path().join("f1.txt"), "content").await?;
+            async_fs::write(root.path().join("f2.txt"), "").await?;
+
+            let mut wd = WalkDir::new(root.path())
+                .with_prefetch_metadata(true)
+                .filter(|entry| async move {
+                    match entry.cached_metadata() {
+                        Some(metadata) if metadata.len() > 0 => Filtering::Continue,
+                        _ => Filtering::Ignore,
+                    }
+                });
+            let entry = wd.next().await.unwrap()?;
+            assert_eq!(entry.path(), root.path().join("f1.txt"));
+            assert_eq!(entry.cached_metadata().map(|m| m.len()), Some(7));
+            assert!(wd.next().await.is_none());
+
+            let mut wd = WalkDir::new(root.path()).filter_min_size(1);
+            let entry = wd.next().await.unwrap()?;
+            assert_eq!(entry.cached_metadata().map(|m| m.len()), Some(7));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn entry_metadata() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::write(root.path().join("f1.txt"), "content").await?;
+
+            let entry = WalkDir::new(root.path()).next().await.unwrap()?;
+            assert!(entry.file_type()?.is_file());
+            assert!(entry.cached_metadata().is_none());
+            assert_eq!(entry.metadata()?.len(), 7);
+            assert_eq!(entry.cached_metadata().map(|m| m.len()), Some(7));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn containing_dir() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a")).await?;
+            async_fs::write(root.path().join("a/f1.txt"), "").await?;
+
+            let mut wd = WalkDir::new(root.path());
+            let entry = wd.next().await.unwrap()?;
+            assert_eq!(entry.containing_dir(), root.path());
+            let entry = wd.next().await.unwrap()?;
+            assert_eq!(entry.containing_dir(), root.path().join("a"));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn entry_file_type_cached() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::write(root.path().join("f1.txt"), "").await?;
+
+            let entry = WalkDir::new(root.path()).next().await.unwrap()?;
+            async_fs::remove_file(entry.path()).await?;
+            assert!(entry.file_type()?.is_file());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn entry_limit_per_dir() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for dir in ["a", "b"] {
+                async_fs::create_dir_all(root.path().join(dir)).await?;
+                for i in 0..5 {
+                    let file = root.path().join(dir).join(format!("f{}.txt", i));
+                    async_fs::write(file, "").await?;
+                }
+            }
+
+            let got = WalkDir::new(root.path())
+                .with_entry_limit_per_dir(2)
+                .with_sort_by_name()
+                .collect_paths()
+                .await?;
+            let want = ["a", "a/f0.txt", "a/f1.txt", "b", "b/f0.txt", "b/f1.txt"]
+                .map(|path| root.path().join(path));
+            assert_eq!(got, want);
+
+            let got = WalkDir::new(root.path())
+                .with_entry_limit_per_dir(1)
+                .with_contents_first(true)
+                .with_sort_by_name()
+                .collect_paths()
+                .await?;
+            assert_eq!(got, [root.path().join("a/f0.txt"), root.path().join("a")]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn max_entries() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for i in 0..10 {
+                async_fs::write(root.path().join(format!("f{}.txt", i)), "").await?;
+            }
+
+            let count = WalkDir::new(root.path())
+                .with_max_entries(3)
+                .count()
+                .await?;
+            assert_eq!(count, 3);
+
+            let count = WalkDir::new(root.path())
+                .with_max_entries(0)
+                .count()
+                .await?;
+            assert_eq!(count, 0);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn deduplicate() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a")).await?;
+            async_fs::create_dir_all(root.path().join("b")).await?;
+            async_fs::write(root.path().join("a/f.txt"), "").await?;
+            async_fs::hard_link(root.path().join("a/f.txt"), root.path().join("b/f.txt")).await?;
+
+            let got = WalkDir::new(root.path())
+                .with_deduplicate(true)
+                .into_file_stream()
+                .try_fold(0, |count, _| Ok(count + 1))
+                .await?;
+            assert_eq!(got, 1);
+
+            let count = WalkDir::new(root.path()).count().await?;
+            assert_eq!(count, 4);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn error_limit() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for i in 0..5 {
+                let link = root.path().join(format!("link{}", i));
+                std::os::unix::fs::symlink(root.path(), link)?;
+            }
+
+            let mut wd = WalkDir::new(root.path())
+                .with_follow_links(true)
+                .with_error_limit(2);
+            let mut errors = 0;
             while let Some(entry) = wd.next().await {
-                let entry = entry.unwrap();
-                got.push(entry.path());
+                if entry.is_err() {
+                    errors += 1;
+                }
+            }
+            assert_eq!(errors, 2);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn async_metadata() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::write(root.path().join("f.txt"), "content").await?;
+
+            let mut wd = WalkDir::new(root.path());
+            let entry = wd.next().await.unwrap()?;
+            assert!(entry.cached_metadata().is_none());
+            assert_eq!(entry.async_metadata().await?.len(), 7);
+            assert_eq!(entry.cached_metadata().map(|m| m.len()), Some(7));
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn resume_from() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a/b")).await?;
+            async_fs::create_dir_all(root.path().join("c")).await?;
+            async_fs::create_dir_all(root.path().join("d")).await?;
+            async_fs::write(root.path().join("a/b/f1.txt"), "").await?;
+            async_fs::write(root.path().join("a/f2.txt"), "").await?;
+            async_fs::write(root.path().join("c/f3.txt"), "").await?;
+
+            // Interrupted while reading "a", after walking "a/b" and "c".
+            let checkpoint = WalkCheckpoint {
+                dirs: vec![
+                    CheckpointDir {
+                        path: root.path().to_owned(),
+                        depth: 0,
+                        walked: vec!["a".into(), "c".into()].into_iter().collect(),
+                    },
+                    CheckpointDir {
+                        path: root.path().join("a"),
+                        depth: 1,
+                        walked: vec!["b".into()].into_iter().collect(),
+                    },
+                ],
+                ..WalkCheckpoint::default()
+            };
+            assert_eq!(checkpoint.pending_dirs().count(), 2);
+
+            let mut got = WalkDir::resume_from(checkpoint).collect_paths().await?;
+            got.sort();
+            let want = vec![root.path().join("a/f2.txt"), root.path().join("d")];
+            assert_eq!(got, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn serde() -> Result<()> {
+        let options = WalkDirOptions {
+            max_depth: Some(2),
+            included_paths: Some(vec!["src".into()]),
+            filter_error_policy: FilterErrorPolicy::Stop,
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&options)?;
+        assert_eq!(serde_json::from_str::<WalkDirOptions>(&json)?, options);
+
+        let checkpoint = WalkCheckpoint {
+            roots: vec!["my_directory".into()],
+            ..Default::default()
+        };
+        let json = serde_json::to_string(&checkpoint)?;
+        assert_eq!(serde_json::from_str::<WalkCheckpoint>(&json)?, checkpoint);
+
+        let json = serde_json::to_string(&Filtering::IgnoreDir)?;
+        assert_eq!(
+            serde_json::from_str::<Filtering>(&json)?,
+            Filtering::IgnoreDir
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn directory_tree_string() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a/b")).await?;
+            async_fs::create_dir_all(root.path().join("c")).await?;
+            async_fs::write(root.path().join("a/b/f1.txt"), "").await?;
+            async_fs::write(root.path().join("a/f2.txt"), "").await?;
+            async_fs::write(root.path().join("f3.txt"), "").await?;
+
+            let got = WalkDir::new(root.path()).directory_tree_string().await?;
+            let want = format!(
+                "{}/\n\
+                 ├── a/\n\
+                 │   ├── b/\n\
+                 │   │   └── f1.txt\n\
+                 │   └── f2.txt\n\
+                 ├── c/\n\
+                 └── f3.txt\n",
+                root.path().display()
+            );
+            assert_eq!(got, want);
+
+            let got = WalkDir::new(root.path())
+                .with_max_depth(0)
+                .directory_tree_string()
+                .await?;
+            let want = format!("{}/\n├── a/\n├── c/\n└── f3.txt\n", root.path().display());
+            assert_eq!(got, want);
+
+            let got = WalkDir::new(root.path())
+                .with_min_depth(1)
+                .directory_tree_string()
+                .await?;
+            let want = format!(
+                "{}/\n    ├── b/\n    │   └── f1.txt\n    └── f2.txt\n",
+                root.path().display()
+            );
+            assert_eq!(got, want);
+
+            #[cfg(unix)]
+            {
+                let link = root.path().join("link");
+                async_fs::unix::symlink(root.path().join("a"), &link).await?;
+                let got = WalkDir::new(&link)
+                    .with_follow_root_links(true)
+                    .directory_tree_string()
+                    .await?;
+                let want = format!("{}/\n├── b/\n│   └── f1.txt\n└── f2.txt\n", link.display());
+                assert_eq!(got, want);
+            }
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn disk_usage() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a")).await?;
+            async_fs::write(root.path().join("a/f1.txt"), [0; 10]).await?;
+            async_fs::write(root.path().join("f2.txt"), [0; 20]).await?;
+
+            let (total, errors) = WalkDir::disk_usage(root.path(), false).await;
+            assert_eq!(total, 30);
+            assert!(errors.is_empty());
+
+            let (_, errors) = WalkDir::disk_usage(root.path(), true).await;
+            assert!(errors.is_empty());
+
+            let (total, errors) = WalkDir::disk_usage(root.path().join("missing"), false).await;
+            assert_eq!(total, 0);
+            assert_eq!(errors.len(), 1);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn snapshot_diff() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a")).await?;
+            async_fs::write(root.path().join("a/f1.txt"), "").await?;
+            async_fs::write(root.path().join("f2.txt"), "").await?;
+
+            let before = WalkDir::snapshot(root.path()).await?;
+            assert_eq!(
+                before,
+                [
+                    root.path().join("a"),
+                    root.path().join("a/f1.txt"),
+                    root.path().join("f2.txt")
+                ]
+            );
+            assert!(WalkDir::diff(&before, &before).is_empty());
+
+            async_fs::rename(root.path().join("a/f1.txt"), root.path().join("f1.txt")).await?;
+            async_fs::remove_file(root.path().join("f2.txt")).await?;
+            async_fs::write(root.path().join("a/f3.txt"), "").await?;
+
+            let after = WalkDir::snapshot(root.path()).await?;
+            let diff = WalkDir::diff(&before, &after);
+            assert_eq!(diff.added, [root.path().join("a/f3.txt")]);
+            assert_eq!(diff.removed, [root.path().join("f2.txt")]);
+            assert_eq!(
+                diff.renamed,
+                [(root.path().join("a/f1.txt"), root.path().join("f1.txt"))]
+            );
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn collect_by_extension() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a.d")).await?;
+            async_fs::write(root.path().join("a.d/f1.txt"), "").await?;
+            async_fs::write(root.path().join("f2.txt"), "").await?;
+            async_fs::write(root.path().join("f3"), "").await?;
+
+            let mut groups = WalkDir::new(root.path()).collect_by_extension().await?;
+            assert_eq!(groups.len(), 2);
+            let txt = groups.get_mut(OsStr::new("txt")).unwrap();
+            txt.sort();
+            assert_eq!(
+                *txt,
+                [root.path().join("a.d/f1.txt"), root.path().join("f2.txt")]
+            );
+            assert_eq!(groups[OsStr::new("")], [root.path().join("f3")]);
+
+            Ok(())
+        })
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn progress_watch() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::write(root.path().join("f1.txt"), "").await?;
+            async_fs::write(root.path().join("f2.txt"), "").await?;
+
+            let (mut wd, progress) = WalkDir::new(root.path()).with_progress_watch();
+            assert_eq!(progress.borrow().entries_yielded, 0);
+            let entry = wd.next().await.unwrap()?;
+            assert_eq!(progress.borrow().entries_yielded, 1);
+            assert_eq!(progress.borrow().current_path, entry.path());
+            assert_eq!(wd.count().await?, 1);
+            assert_eq!(progress.borrow().entries_yielded, 2);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn cancellation_token() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            for i in 0..5 {
+                async_fs::write(root.path().join(format!("f{}.txt", i)), "").await?;
             }
+
+            let cancelled = Arc::new(AtomicBool::new(false));
+            let mut wd = WalkDir::new(root.path()).with_cancellation_token(cancelled.clone());
+            assert!(wd.next().await.is_some());
+            cancelled.store(true, Ordering::Relaxed);
+            assert!(wd.next().await.is_none());
+            assert!(wd.next().await.is_none());
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn find_duplicates() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a")).await?;
+            async_fs::write(root.path().join("a/f1.txt"), "same").await?;
+            async_fs::write(root.path().join("f2.txt"), "same").await?;
+            async_fs::write(root.path().join("f3.txt"), "other").await?;
+
+            let groups = WalkDir::new(root.path())
+                .find_duplicates(2, async_fs::read)
+                .await?;
+            assert_eq!(groups.len(), 1);
+            let mut paths = groups[&b"same"[..]].clone();
+            paths.sort();
+            let want = vec![root.path().join("a/f1.txt"), root.path().join("f2.txt")];
+            assert_eq!(paths, want);
+
+            let err = WalkDir::new(root.path())
+                .find_duplicates(2, |_| async { Err(std::io::Error::other("hash failed")) })
+                .await
+                .unwrap_err();
+            assert_eq!(err.io_error().to_string(), "hash failed");
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn max_file_size() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a")).await?;
+            async_fs::write(root.path().join("a/f1.txt"), [0; 10]).await?;
+            async_fs::write(root.path().join("f2.txt"), [0; 20]).await?;
+
+            let mut wd = WalkDir::new(root.path()).with_max_file_size(10);
+            let mut paths = Vec::new();
+            while let Some(entry) = wd.next().await {
+                let entry = entry?;
+                if entry.file_type()?.is_file() {
+                    assert_eq!(entry.cached_metadata().map(|m| m.len()), Some(10));
+                }
+                paths.push(entry.path());
+            }
+            paths.sort();
+            let want = vec![root.path().join("a"), root.path().join("a/f1.txt")];
+            assert_eq!(paths, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn min_file_size() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a")).await?;
+            async_fs::write(root.path().join("a/f1.txt"), [0; 10]).await?;
+            async_fs::write(root.path().join("f2.txt"), [0; 20]).await?;
+            async_fs::write(root.path().join("f3.txt"), [0; 30]).await?;
+
+            let mut paths = WalkDir::new(root.path())
+                .with_min_file_size(15)
+                .collect_paths()
+                .await?;
+            paths.sort();
+            let want = vec![
+                root.path().join("a"),
+                root.path().join("f2.txt"),
+                root.path().join("f3.txt"),
+            ];
+            assert_eq!(paths, want);
+
+            let mut paths = WalkDir::new(root.path())
+                .with_min_file_size(15)
+                .with_max_file_size(25)
+                .collect_paths()
+                .await?;
+            paths.sort();
+            let want = vec![root.path().join("a"), root.path().join("f2.txt")];
+            assert_eq!(paths, want);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn flat_map_async() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a")).await?;
+            async_fs::write(root.path().join("a/f1.txt"), "l1\nl2\n").await?;
+            async_fs::write(root.path().join("f2.txt"), "l3\n").await?;
+
+            let mut lines: Vec<String> = WalkDir::new(root.path())
+                .flat_map_async(|entry| async move {
+                    let lines = match entry {
+                        Ok(entry) if entry.file_type().is_ok_and(|ft| ft.is_file()) => {
+                            let content = async_fs::read_to_string(entry.path()).await;
+                            content.unwrap().lines().map(String::from).collect()
+                        }
+                        _ => Vec::new(),
+                    };
+                    futures_lite::stream::iter(lines)
+                })
+                .collect()
+                .await;
+            lines.sort();
+            assert_eq!(lines, ["l1", "l2", "l3"]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn path_transform() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a")).await?;
+            async_fs::write(root.path().join("a/f1.txt"), "").await?;
+
+            let prefix = root.path().to_owned();
+            let mut wd = WalkDir::new(root.path())
+                .with_sort_by_name()
+                .with_path_transform(move |path| path.strip_prefix(&prefix).unwrap().to_owned());
+            let entry = wd.next().await.unwrap()?;
+            assert_eq!(entry.path(), Path::new("a"));
+            assert_eq!(entry.raw().path(), root.path().join("a"));
+            assert_eq!(entry.depth(), 0);
+            let entry = wd.next().await.unwrap()?;
+            assert_eq!(entry.path(), Path::new("a/f1.txt"));
+            assert!(wd.next().await.is_none());
+
+            Ok(())
+        })
+    }
+
+    #[cfg(feature = "async_iterator")]
+    #[test]
+    fn async_iterator() -> Result<()> {
+        use std::async_iter::AsyncIterator;
+
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::write(root.path().join("f1.txt"), "").await?;
+
+            let mut wd = WalkDir::new(root.path());
+            let entry = futures_lite::future::poll_fn(|cx| {
+                AsyncIterator::poll_next(std::pin::Pin::new(&mut wd), cx)
+            })
+            .await;
+            assert_eq!(entry.unwrap()?.file_name(), "f1.txt");
+
+            Ok(())
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn follow_root_links() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let d1 = root.path().join("d1");
+            let link = root.path().join("link");
+            async_fs::create_dir_all(d1.join("d2")).await?;
+            async_fs::unix::symlink(&d1, &link).await?;
+            async_fs::unix::symlink(&d1, d1.join("d2/cycle")).await?;
+
+            let got = WalkDir::new(&link).collect_paths().await?;
+            assert_eq!(got.len(), 2);
+            assert!(got.iter().all(|path| path.starts_with(&link)));
+
+            let got = WalkDir::new(&link)
+                .with_follow_root_links(true)
+                .collect_paths()
+                .await?;
+            let d1 = d1.canonicalize()?;
+            assert_eq!(got, [d1.join("d2"), d1.join("d2/cycle")]);
+
+            Ok(())
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn with_permissions_filter() -> Result<()> {
+        use std::fs::Permissions;
+        use std::os::unix::fs::PermissionsExt;
+
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("a/f1.txt");
+            let f2 = root.path().join("f2.txt");
+            async_fs::create_dir_all(root.path().join("a")).await?;
+            async_fs::write(&f1, "").await?;
+            async_fs::write(&f2, "").await?;
+            async_fs::set_permissions(&f1, Permissions::from_mode(0o444)).await?;
+            async_fs::set_permissions(&f2, Permissions::from_mode(0o640)).await?;
+
+            let got = WalkDir::new(root.path())
+                .with_permissions_filter(|p| p.readonly())
+                .collect_paths()
+                .await?;
+            assert_eq!(got, [f1]);
+
+            let got = WalkDir::new(root.path())
+                .with_unix_permissions_filter(|mode| mode & 0o777 == 0o640)
+                .collect_paths()
+                .await?;
+            assert_eq!(got, [f2]);
+
+            Ok(())
+        })
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn with_unix_mode_filter() -> Result<()> {
+        use std::fs::Permissions;
+        use std::os::unix::fs::PermissionsExt;
+
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            let f1 = root.path().join("a/f1.txt");
+            let f2 = root.path().join("f2.txt");
+            async_fs::create_dir_all(root.path().join("a")).await?;
+            async_fs::write(&f1, "").await?;
+            async_fs::write(&f2, "").await?;
+            async_fs::set_permissions(&f1, Permissions::from_mode(0o666)).await?;
+            async_fs::set_permissions(&f2, Permissions::from_mode(0o4744)).await?;
+
+            let mut got = WalkDir::new(root.path())
+                .filter_world_writable()
+                .collect_paths()
+                .await?;
             got.sort();
+            assert_eq!(got, [root.path().join("a"), f1]);
+
+            let mut got = WalkDir::new(root.path())
+                .filter_setuid()
+                .collect_paths()
+                .await?;
+            got.sort();
+            assert_eq!(got, [root.path().join("a"), f2]);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn last_n_by_modified_time() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::create_dir_all(root.path().join("a")).await?;
+            let now = SystemTime::now();
+            for (i, name) in ["a/f1.txt", "f2.txt", "f3.txt", "a/f4.txt"]
+                .iter()
+                .enumerate()
+            {
+                let file = std::fs::File::create(root.path().join(name))?;
+                file.set_modified(now - Duration::from_secs(60 * i as u64))?;
+            }
+
+            let got = WalkDir::new(root.path()).last_n_by_modified_time(3).await?;
+            let got: Vec<_> = got.iter().map(|entry| entry.path()).collect();
+            let want = ["a/f1.txt", "f2.txt", "f3.txt"].map(|name| root.path().join(name));
             assert_eq!(got, want);
 
+            assert!(WalkDir::new(root.path())
+                .last_n_by_modified_time(0)
+                .await?
+                .is_empty());
+
+            let got = WalkDir::new(root.path())
+                .last_n_by_modified_time(usize::MAX)
+                .await?;
+            assert_eq!(got.len(), 4);
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn filter_retaining_entry() -> Result<()> {
+        block_on(async {
+            let root = tempfile::tempdir()?;
+            async_fs::write(root.path().join("f1.txt"), "").await?;
+
+            let retained = Arc::new(Mutex::new(Vec::new()));
+            let mut wd = WalkDir::new(root.path()).filter({
+                let retained = retained.clone();
+                move |entry| {
+                    retained.lock().unwrap().push(entry);
+                    async { Filtering::Continue }
+                }
+            });
+            let entry = wd.next().await.unwrap()?;
+            assert_eq!(entry.path(), root.path().join("f1.txt"));
+            assert!(entry.cached_metadata().is_none());
+            assert!(wd.next().await.is_none());
+            assert_eq!(retained.lock().unwrap().len(), 1);
+            assert_eq!(entry.into_inner().file_name(), "f1.txt");
+
+            // The entry is read again with its cached metadata.
+            let entry = WalkDir::new(root.path())
+                .with_prefetch_metadata(true)
+                .find_first(|entry| {
+                    let retained = retained.clone();
+                    async move {
+                        retained.lock().unwrap().push(entry);
+                        true
+                    }
+                })
+                .await?
+                .unwrap();
+            assert_eq!(entry.depth(), 0);
+            assert!(entry.cached_metadata().is_some());
+
             Ok(())
         })
     }
@@ -0,0 +1,222 @@
+// Copyright 2020 Ririsoft <riri@ririsoft.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_lite::stream::Stream;
+
+use crate::{Result, WalkDirError};
+
+/// An in-memory tree, mapping the path of each directory to its children.
+pub type VirtualTree = HashMap<PathBuf, Vec<(OsString, VirtualFileType)>>;
+
+/// The type of a [`VirtualEntry`].
+///
+/// Mirrors the accessors of [`std::fs::FileType`], which cannot be built
+/// without the file system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VirtualFileType {
+    /// A regular file.
+    File,
+    /// A directory, whose children are looked up in the tree.
+    Dir,
+    /// A symbolic link, never followed.
+    Symlink,
+}
+
+impl VirtualFileType {
+    /// Returns whether this is a regular file.
+    pub fn is_file(self) -> bool {
+        self == Self::File
+    }
+
+    /// Returns whether this is a directory.
+    pub fn is_dir(self) -> bool {
+        self == Self::Dir
+    }
+
+    /// Returns whether this is a symbolic link.
+    pub fn is_symlink(self) -> bool {
+        self == Self::Symlink
+    }
+}
+
+/// An entry of a [`VirtualWalkDir`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VirtualEntry {
+    dir: Arc<Path>,
+    name: OsString,
+    file_type: VirtualFileType,
+    depth: usize,
+}
+
+impl VirtualEntry {
+    /// Returns the full path of the entry.
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(&self.name)
+    }
+
+    /// Returns the file name of the entry.
+    pub fn file_name(&self) -> &OsStr {
+        &self.name
+    }
+
+    /// Returns the type of the entry.
+    pub fn file_type(&self) -> VirtualFileType {
+        self.file_type
+    }
+
+    /// Returns the depth of the entry, the children of the root being at
+    /// depth 0.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the directory containing the entry.
+    pub fn containing_dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+/// A `Stream` of [`VirtualEntry`] generated from recursively traversing an
+/// in-memory tree, to test code consuming a walk without touching the file
+/// system.
+///
+/// Entries are returned depth first, in the order of the tree. A directory
+/// missing from the tree is empty, except for the root which yields a
+/// [`NotFound`](io::ErrorKind::NotFound) error. A child whose name is not a
+/// single path component, such as `..`, `a/b` or an absolute path, would lead
+/// out of its directory: an [`InvalidInput`](io::ErrorKind::InvalidInput)
+/// error is yielded in its place.
+///
+/// # Example
+///
+/// ```
+/// use std::collections::HashMap;
+/// use std::path::PathBuf;
+///
+/// use async_walkdir::{VirtualFileType, VirtualWalkDir};
+/// use futures_lite::future::block_on;
+/// use futures_lite::stream::StreamExt;
+///
+/// let mut tree = HashMap::new();
+/// tree.insert(
+///     PathBuf::from("/root"),
+///     vec![("a".into(), VirtualFileType::Dir)],
+/// );
+/// tree.insert(
+///     PathBuf::from("/root/a"),
+///     vec![("f.txt".into(), VirtualFileType::File)],
+/// );
+///
+/// block_on(async {
+///     let mut entries = VirtualWalkDir::new(tree, "/root");
+///     while let Some(entry) = entries.next().await {
+///         println!("{}", entry.unwrap().path().display());
+///     }
+/// });
+/// ```
+#[derive(Debug)]
+pub struct VirtualWalkDir {
+    tree: VirtualTree,
+    root: Option<PathBuf>,
+    // Directories being traversed, the current one last.
+    dirs: Vec<VirtualDir>,
+}
+
+#[derive(Debug)]
+struct VirtualDir {
+    path: Arc<Path>,
+    depth: usize,
+    children: std::vec::IntoIter<(OsString, VirtualFileType)>,
+}
+
+impl VirtualWalkDir {
+    /// Returns a new `VirtualWalkDir` starting at `root` in `tree`.
+    pub fn new(tree: VirtualTree, root: impl AsRef<Path>) -> Self {
+        Self {
+            tree,
+            root: Some(root.as_ref().to_owned()),
+            dirs: Vec::new(),
+        }
+    }
+
+    // Returns a directory to traverse, empty when missing from the tree.
+    fn open(&self, path: PathBuf, depth: usize) -> VirtualDir {
+        let children = self.tree.get(&path).cloned().unwrap_or_default();
+        VirtualDir {
+            path: path.into(),
+            depth,
+            children: children.into_iter(),
+        }
+    }
+}
+
+impl Stream for VirtualWalkDir {
+    type Item = Result<VirtualEntry>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(root) = self.root.take() {
+            if !self.tree.contains_key(&root) {
+                let err = io::Error::new(io::ErrorKind::NotFound, "no such directory in the tree");
+                return Poll::Ready(Some(Err(WalkDirError::new(root, err))));
+            }
+            let dir = self.open(root, 0);
+            self.dirs.push(dir);
+        }
+        loop {
+            let dir = match self.dirs.last_mut() {
+                Some(dir) => dir,
+                None => return Poll::Ready(None),
+            };
+            let (name, file_type) = match dir.children.next() {
+                Some(child) => child,
+                None => {
+                    self.dirs.pop();
+                    continue;
+                }
+            };
+            if !is_file_name(&name) {
+                let err = io::Error::new(io::ErrorKind::InvalidInput, "invalid file name");
+                let path = dir.path.to_path_buf();
+                return Poll::Ready(Some(Err(WalkDirError::new(path, err))));
+            }
+            let entry = VirtualEntry {
+                dir: dir.path.clone(),
+                name,
+                file_type,
+                depth: dir.depth,
+            };
+            if file_type.is_dir() {
+                let child = self.open(entry.path(), entry.depth + 1);
+                self.dirs.push(child);
+            }
+            return Poll::Ready(Some(Ok(entry)));
+        }
+    }
+}
+
+// Returns whether `name` is a single normal path component.
+fn is_file_name(name: &OsStr) -> bool {
+    let mut components = Path::new(name).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    )
+}
@@ -0,0 +1,70 @@
+// Copyright 2020 Ririsoft <riri@ririsoft.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::stream::FusedStream;
+use futures_lite::future;
+use futures_lite::stream::Stream;
+
+use crate::{Entry, Result, WalkDir};
+
+/// A [`WalkDir`] whose next item can be inspected without consuming it.
+///
+/// Returned by [`WalkDir::peekable`].
+pub struct PeekableWalkDir {
+    walk: WalkDir,
+    // The item read ahead by `peek`, `Some(None)` once the walk is over.
+    peeked: Option<Option<Result<Entry>>>,
+}
+
+impl PeekableWalkDir {
+    pub(crate) fn new(walk: WalkDir) -> Self {
+        Self { walk, peeked: None }
+    }
+
+    /// Returns the next item without consuming it, or `None` once the walk
+    /// is over.
+    ///
+    /// The item is read from the walk on the first call, and then yielded by
+    /// the stream.
+    pub async fn peek(&mut self) -> Option<&Result<Entry>> {
+        if self.peeked.is_none() {
+            let walk = &mut self.walk;
+            let item = future::poll_fn(|cx| Pin::new(&mut *walk).poll_next(cx)).await;
+            self.peeked = Some(item);
+        }
+        self.peeked.as_ref().and_then(Option::as_ref)
+    }
+}
+
+impl Stream for PeekableWalkDir {
+    type Item = Result<Entry>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(item) = self.peeked.take() {
+            return Poll::Ready(item);
+        }
+        Pin::new(&mut self.walk).poll_next(cx)
+    }
+}
+
+impl FusedStream for PeekableWalkDir {
+    fn is_terminated(&self) -> bool {
+        match &self.peeked {
+            Some(item) => item.is_none(),
+            None => self.walk.is_terminated(),
+        }
+    }
+}
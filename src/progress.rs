@@ -0,0 +1,27 @@
+// Copyright 2020 Ririsoft <riri@ririsoft.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::path::PathBuf;
+
+/// The progress of a walk.
+///
+/// Broadcast by [`WalkDir::with_progress_watch`](crate::WalkDir::with_progress_watch)
+/// each time an entry is yielded.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WalkProgress {
+    /// The number of entries yielded so far.
+    pub entries_yielded: u64,
+    /// The path of the last entry yielded, empty before the first one.
+    pub current_path: PathBuf,
+}
@@ -0,0 +1,53 @@
+// Copyright 2020 Ririsoft <riri@ririsoft.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::time::{Duration, Instant};
+
+/// A token bucket limiting the rate of a walk.
+///
+/// The bucket holds up to one second worth of entries, so that short bursts
+/// are allowed while the average rate stays under the limit.
+#[derive(Debug, Clone)]
+pub(crate) struct RateLimiter {
+    // Entries per second.
+    rate: f64,
+    // Available entries, negative when entries are waiting.
+    tokens: f64,
+    refilled: Instant,
+}
+
+impl RateLimiter {
+    pub(crate) fn new(rate: f64) -> Self {
+        Self {
+            rate,
+            tokens: rate.max(1.0),
+            refilled: Instant::now(),
+        }
+    }
+
+    /// Takes a token at `now`, returning how long to wait before using it.
+    ///
+    /// The delay saturates at [`Duration::MAX`] for the tiniest rates.
+    pub(crate) fn acquire(&mut self, now: Instant) -> Option<Duration> {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate.max(1.0));
+        self.refilled = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            None
+        } else {
+            let delay = Duration::try_from_secs_f64(-self.tokens / self.rate);
+            Some(delay.unwrap_or(Duration::MAX))
+        }
+    }
+}
@@ -0,0 +1,126 @@
+// Copyright 2020 Ririsoft <riri@ririsoft.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::stream::FusedStream;
+use futures_lite::stream::Stream;
+
+use crate::{Entry, Result, WalkDir};
+
+/// The root of a walk, yielded by [`RootedWalkDir`].
+///
+/// Unlike an [`Entry`], it is not read from the listing of a directory, as a
+/// root may have no parent such as `/`: it has no [`std::fs::DirEntry`] and is
+/// typed from the metadata of its path, not following symbolic links.
+#[derive(Debug, Clone)]
+pub struct RootEntry {
+    path: PathBuf,
+    metadata: fs::Metadata,
+}
+
+impl RootEntry {
+    // Reads the entry of the root at `path`.
+    pub(crate) fn read(path: PathBuf) -> io::Result<Self> {
+        let metadata = fs::symlink_metadata(&path)?;
+        Ok(Self { path, metadata })
+    }
+
+    /// Returns the path of the root, as given to the walk or canonicalized
+    /// with [`WalkDir::with_follow_root_links`].
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path of the root.
+    pub fn into_path(self) -> PathBuf {
+        self.path
+    }
+
+    /// Returns the file type of the root, symbolic links not being traversed.
+    pub fn file_type(&self) -> fs::FileType {
+        self.metadata.file_type()
+    }
+
+    /// Returns the metadata of the root, read when walking it.
+    pub fn metadata(&self) -> &fs::Metadata {
+        &self.metadata
+    }
+}
+
+/// An item of [`RootedWalkDir`]: a root, or an entry of its tree.
+#[derive(Debug)]
+pub enum RootedEntry {
+    /// The root of a walk.
+    Root(RootEntry),
+    /// An entry under a root, as yielded by [`WalkDir`].
+    Entry(Entry),
+}
+
+impl RootedEntry {
+    /// Returns the full path of the root or entry.
+    pub fn path(&self) -> PathBuf {
+        match self {
+            RootedEntry::Root(root) => root.path.clone(),
+            RootedEntry::Entry(entry) => entry.path(),
+        }
+    }
+
+    /// Returns the depth relative to the walk root, 0 for the root itself as
+    /// for its direct children.
+    pub fn depth(&self) -> usize {
+        match self {
+            RootedEntry::Root(_) => 0,
+            RootedEntry::Entry(entry) => entry.depth(),
+        }
+    }
+
+    /// Returns the entry, or `None` for a root.
+    pub fn into_entry(self) -> Option<Entry> {
+        match self {
+            RootedEntry::Root(_) => None,
+            RootedEntry::Entry(entry) => Some(entry),
+        }
+    }
+}
+
+/// A [`WalkDir`] yielding its roots along with their content.
+///
+/// Returned by [`WalkDir::with_root_entry`].
+pub struct RootedWalkDir {
+    walk: WalkDir,
+}
+
+impl RootedWalkDir {
+    pub(crate) fn new(walk: WalkDir) -> Self {
+        Self { walk }
+    }
+}
+
+impl Stream for RootedWalkDir {
+    type Item = Result<RootedEntry>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.walk.poll_item(cx)
+    }
+}
+
+impl FusedStream for RootedWalkDir {
+    fn is_terminated(&self) -> bool {
+        self.walk.is_terminated()
+    }
+}
@@ -0,0 +1,64 @@
+// Copyright 2020 Ririsoft <riri@ririsoft.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Statistics about a walk in progress.
+///
+/// Returned by [`WalkDir::with_stats`](crate::WalkDir::with_stats), the
+/// counters are updated as the stream is polled and can be read from any
+/// task at any time.
+#[derive(Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WalkDirStats {
+    entries_visited: AtomicU64,
+    dirs_visited: AtomicU64,
+    errors_encountered: AtomicU64,
+    bytes_examined: AtomicU64,
+}
+
+impl WalkDirStats {
+    /// Returns the number of entries read from directories, filtered or not.
+    pub fn entries_visited(&self) -> u64 {
+        self.entries_visited.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of directories opened, roots included.
+    pub fn dirs_visited(&self) -> u64 {
+        self.dirs_visited.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of errors yielded.
+    pub fn errors_encountered(&self) -> u64 {
+        self.errors_encountered.load(Ordering::Relaxed)
+    }
+
+    /// Returns the total size in bytes of the regular files visited.
+    pub fn bytes_examined(&self) -> u64 {
+        self.bytes_examined.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn add_entry(&self, bytes: u64) {
+        self.entries_visited.fetch_add(1, Ordering::Relaxed);
+        self.bytes_examined.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_dir(&self) {
+        self.dirs_visited.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_error(&self) {
+        self.errors_encountered.fetch_add(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,71 @@
+// Copyright 2020 Ririsoft <riri@ririsoft.com>
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Platform specific file system helpers.
+
+use std::fs::DirEntry;
+use std::io::Result;
+use std::path::Path;
+
+/// Returns an identifier of the file at `path`, unique within the system.
+///
+/// Symbolic links are followed.
+#[cfg(unix)]
+pub(crate) fn file_id(path: &Path) -> Result<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+
+    let metadata = std::fs::metadata(path)?;
+    Ok((metadata.dev(), metadata.ino()))
+}
+
+/// Returns an identifier of the file at `path`, unique within the system.
+///
+/// Symbolic links are followed.
+#[cfg(windows)]
+pub(crate) fn file_id(path: &Path) -> Result<(u64, u64)> {
+    let handle = winapi_util::Handle::from_path_any(path)?;
+    let info = winapi_util::file::information(&handle)?;
+    Ok((info.volume_serial_number(), info.file_index()))
+}
+
+/// Returns an identifier of the file at `path`, unique within the system.
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn file_id(_path: &Path) -> Result<(u64, u64)> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "file identifiers are not supported on this platform",
+    ))
+}
+
+/// Returns the identifier of the device holding the file at `path`.
+pub(crate) fn device_id(path: &Path) -> Result<u64> {
+    file_id(path).map(|(device, _)| device)
+}
+
+/// Returns whether `entry` is hidden, that is its name starts with a dot.
+#[cfg(not(windows))]
+pub(crate) fn is_hidden(entry: &DirEntry) -> bool {
+    entry.file_name().to_string_lossy().starts_with('.')
+}
+
+/// Returns whether `entry` has the hidden attribute.
+#[cfg(windows)]
+pub(crate) fn is_hidden(entry: &DirEntry) -> bool {
+    use std::os::windows::fs::MetadataExt;
+
+    const FILE_ATTRIBUTE_HIDDEN: u32 = 0x2;
+    entry
+        .metadata()
+        .is_ok_and(|m| m.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0)
+}